@@ -0,0 +1,60 @@
+#import bevy_sprite::{
+    mesh2d_functions as mesh_functions,
+    mesh2d_view_bindings::view,
+}
+
+#ifdef TONEMAP_IN_SHADER
+#import bevy_core_pipeline::tonemapping
+#endif
+
+struct ChunkMaterial {
+    // color of full sky light at the current time of day
+    sky_light: vec4<f32>,
+};
+
+@group(2) @binding(0) var<uniform> material: ChunkMaterial;
+@group(2) @binding(1) var atlas: texture_2d<f32>;
+@group(2) @binding(2) var atlas_sampler: sampler;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(4) block_light: vec4<f32>,
+    @location(5) sky_light: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) block_light: vec4<f32>,
+    @location(2) sky_light: f32,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+
+    let world_from_local = mesh_functions::get_world_from_local(vertex.instance_index);
+    let world_position = mesh_functions::mesh2d_position_local_to_world(
+        world_from_local,
+        vec4<f32>(vertex.position, 1.0)
+    );
+    out.position = mesh_functions::mesh2d_position_world_to_clip(world_position);
+    out.uv = vertex.uv;
+    out.block_light = vertex.block_light;
+    out.sky_light = vertex.sky_light;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // block and sky light mix like two block lights do, by the brightest of each channel
+    let light = max(in.block_light.rgb, material.sky_light.rgb * in.sky_light);
+    var color = textureSample(atlas, atlas_sampler, in.uv) * vec4<f32>(light, 1.0);
+
+#ifdef TONEMAP_IN_SHADER
+    color = tonemapping::tone_mapping(color, view.color_grading);
+#endif
+    return color;
+}
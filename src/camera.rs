@@ -1,6 +1,11 @@
 use bevy::prelude::*;
 
-use crate::player::Player;
+use crate::{player::Player, world::clock::WorldClock};
+
+/// Background behind the world at noon, at midnight and halfway through sunrise and sunset
+const DAY_SKY: Color = Color::hsl(178., 0.45, 0.43);
+const NIGHT_SKY: Color = Color::hsl(228., 0.4, 0.07);
+const TWILIGHT_SKY: Color = Color::hsl(22., 0.6, 0.5);
 
 pub struct CameraPlugin;
 
@@ -8,7 +13,7 @@ impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_systems(Startup, spawn_camera)
-            .add_systems(Update, follow_player);
+            .add_systems(Update, (follow_player, update_sky_color));
     }
 }
 
@@ -21,7 +26,18 @@ fn spawn_camera(mut commands: Commands) {
             ..OrthographicProjection::default_2d()
         },
     ));
-    commands.insert_resource(ClearColor(Color::hsl(178., 0.45, 0.43)));
+    commands.insert_resource(ClearColor(DAY_SKY));
+}
+
+fn update_sky_color(
+    mut clear_color: ResMut<ClearColor>,
+    clock: Res<WorldClock>,
+) {
+    // mixed in srgb, hues would swing through unrelated colors
+    let sky = Srgba::from(NIGHT_SKY)
+        .mix(&Srgba::from(DAY_SKY), clock.sun())
+        .mix(&Srgba::from(TWILIGHT_SKY), clock.twilight() * 0.6);
+    clear_color.0 = sky.into();
 }
 
 fn follow_player(
@@ -4,16 +4,16 @@ pub mod crafting;
 use crafting::CraftingPlugin;
 
 pub mod item;
-use item::{Item, ItemPlugin};
+use item::{Item, ItemDatabase};
+
+use crate::registry::RegistriesReloaded;
 
 pub struct InventoryPlugin;
 
 impl Plugin for InventoryPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((
-            ItemPlugin,
-            CraftingPlugin
-        ));
+        app.add_plugins(CraftingPlugin);
+        app.add_systems(Update, refresh_inventory_items);
     }
 }
 
@@ -21,16 +21,19 @@ impl Plugin for InventoryPlugin {
 pub struct ItemSlot {
     pub item: Option<Item>,
     pub amount: u32,
+    /// Uses taken off the durability of a tool in this slot
+    pub wear: u32,
 }
 
 impl ItemSlot {
     fn new() -> Self {
-        ItemSlot { item: None, amount: 0 }
+        ItemSlot { item: None, amount: 0, wear: 0 }
     }
 
     pub fn clear(&mut self) {
         self.amount = 0;
         self.item = None;
+        self.wear = 0;
     }
 }
 
@@ -87,6 +90,16 @@ impl Inventory {
         }
     }
 
+    /// Adds as many of `amount` items as there is room for, and returns how many are left over
+    pub fn add_items(&mut self, item: Item, mut amount: u32) -> u32 {
+        while amount > 0 && self.has_room(item) {
+            self.add_item(item);
+            amount -= 1;
+        }
+
+        amount
+    }
+
     pub fn has_item(&self, item: Item, amount: u32) -> bool {
         let mut sum = 0;
 
@@ -115,6 +128,17 @@ impl Inventory {
         }
     }
 
+    /// Uses up one point of durability of the tool in a slot, and removes the tool once it breaks
+    pub fn wear_tool_in_slot(&mut self, slot_id: usize) {
+        let slot = &mut self.items[slot_id];
+        let Some(tool) = slot.item.and_then(|item| item.tool()) else { return };
+
+        slot.wear += 1;
+        if slot.wear >= tool.durability {
+            slot.clear();
+        }
+    }
+
     pub fn remove_item_from_slot(&mut self, slot_id: usize) {
         let slot= &mut self.items[slot_id]; 
         slot.amount -= 1;
@@ -122,4 +146,21 @@ impl Inventory {
             slot.clear();
         }
     }
-}
\ No newline at end of file
+}
+
+/// Slots hold copies of items, so after a reload they fetch them again. Amounts and wear are kept
+fn refresh_inventory_items(
+    mut ev_reloaded: EventReader<RegistriesReloaded>,
+    mut q_inventories: Query<&mut Inventory>,
+    item_database: Res<ItemDatabase>,
+) {
+    if ev_reloaded.read().count() == 0 { return; }
+
+    for mut inventory in q_inventories.iter_mut() {
+        for slot in inventory.items.iter_mut() {
+            if let Some(item) = slot.item.as_mut() {
+                *item = item_database.get_by_id(item.id);
+            }
+        }
+    }
+}
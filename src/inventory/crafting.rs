@@ -1,9 +1,13 @@
-use std::{fs::File, io::Read};
+use std::{fs, path::Path};
 
 use bevy::prelude::*;
-use serde_json::{Map, Value};
+use serde::Deserialize;
 
-use crate::player::Player;
+use crate::{
+    pack::ContentPacks,
+    player::Player,
+    registry::{DataFile, RegistriesReloaded, RegistryFiles, RECIPES_FILE}
+};
 
 use super::{item::ItemDatabase, Inventory};
 
@@ -14,7 +18,7 @@ impl Plugin for CraftingPlugin {
         app.add_event::<CraftItem>();
         app
             .add_systems(Startup, init_database)
-            .add_systems(Update, craft_item);
+            .add_systems(Update, (craft_item, reload_recipes));
     }
 }
 
@@ -43,33 +47,84 @@ impl CraftingRecipeDatabase {
     }
 }
 
+#[derive(Deserialize)]
+struct RecipeData {
+    inputs: Vec<String>,
+    inputs_amount: Vec<u32>,
+    output: String,
+    output_amount: u32,
+}
+
+#[derive(Deserialize)]
+struct RecipesData {
+    recipes: Vec<RecipeData>,
+}
+
+/// Recipes of every pack, `read` giving the contents of a file. Items are named in the files,
+/// recipes naming an unknown one are left out
+fn load_recipes(
+    packs: &ContentPacks,
+    item_database: &ItemDatabase,
+    read: impl Fn(&Path) -> Result<String, String>,
+) -> CraftingRecipeDatabase {
+    let item_id = |name: &str| item_database.get_by_name(name).map(|item| item.id).ok_or_else(|| {
+        error!("unknown item \"{name}\" in a crafting recipe");
+    });
+
+    let mut recipes: Vec<CraftingRecipe> = vec![];
+    for (_, path) in packs.files(RECIPES_FILE) {
+        let data = match read(&path).and_then(|string| serde_json::from_str::<RecipesData>(&string).map_err(|err| err.to_string())) {
+            Ok(data) => data,
+            Err(err) => {
+                error!("{}: {err}", path.display());
+                continue;
+            },
+        };
+
+        for recipe in data.recipes {
+            let Ok(inputs) = recipe.inputs.iter().map(|name| item_id(name)).collect::<Result<Vec<u32>, _>>() else { continue };
+            let Ok(output) = item_id(&recipe.output) else { continue };
+
+            let recipe = CraftingRecipe {
+                inputs,
+                inputs_amount: recipe.inputs_amount,
+                output,
+                output_amount: recipe.output_amount,
+            };
+
+            // a later recipe for the same item replaces the earlier one, which is how packs change recipes
+            match recipes.iter().position(|other| other.output == output) {
+                Some(index) => recipes[index] = recipe,
+                None => recipes.push(recipe),
+            }
+        }
+    }
+
+    CraftingRecipeDatabase { recipes }
+}
+
 fn init_database(
     mut commands: Commands,
+    item_database: Res<ItemDatabase>,
+    packs: Res<ContentPacks>,
 ) {
-    let mut file = File::open("assets/crafting_recipes_data.json").unwrap();
-
-    let mut string = String::new();
-    let _ = file.read_to_string(&mut string);
-
-    let arr = serde_json::from_str::<Map<String, Value>>(&string).unwrap();
-    let arr = arr.get("recipes").unwrap().as_array().unwrap();
-
-    let mut recipes = vec![];
-    for recipe in arr.iter() {
-        let inputs: Vec<u32> = recipe.get("inputs").unwrap().as_array().unwrap().iter().map(|id| id.as_u64().unwrap() as u32).collect();
-        let inputs_amount: Vec<u32> = recipe.get("inputs_amount").unwrap().as_array().unwrap().iter().map(|id| id.as_u64().unwrap() as u32).collect();
-        let output = recipe.get("output").unwrap().as_u64().unwrap() as u32;
-        let output_amount = recipe.get("output_amount").unwrap().as_u64().unwrap() as u32;
-
-        recipes.push(CraftingRecipe {
-            inputs,
-            inputs_amount,
-            output,
-            output_amount,
-        });
-    }
+    commands.insert_resource(load_recipes(&packs, &item_database, |path| {
+        fs::read_to_string(path).map_err(|err| err.to_string())
+    }));
+}
+
+/// Recipes are read again with the block and item data, the recipe file may be what changed
+fn reload_recipes(
+    mut ev_reloaded: EventReader<RegistriesReloaded>,
+    mut recipe_database: ResMut<CraftingRecipeDatabase>,
+    item_database: Res<ItemDatabase>,
+    packs: Res<ContentPacks>,
+    files: Res<RegistryFiles>,
+    data_files: Res<Assets<DataFile>>,
+) {
+    if ev_reloaded.read().count() == 0 { return; }
 
-    commands.insert_resource(CraftingRecipeDatabase { recipes });
+    *recipe_database = load_recipes(&packs, &item_database, |path| files.read(&data_files, path));
 }
 
 #[derive(Event)]
@@ -1,24 +1,56 @@
-use std::{fs::File, io::Read};
+use std::collections::HashMap;
 
 use bevy::prelude::*;
-use serde_json::Value;
+use serde::{Deserialize, Deserializer};
 
-pub struct ItemPlugin;
-
-impl Plugin for ItemPlugin {
-    fn build(&self, app: &mut App) {
-        app.add_systems(Startup, init_database);
-    }
-}
+use crate::{registry::qualify, world::chunk::block::{Block, BlockCategory}};
 
 #[derive(Clone, Copy, PartialEq, Debug, Default)]
 pub enum ItemType {
     Block(u32),
-    Tool,
+    /// Id of the liquid block inside, if it's not empty
+    Bucket(Option<u32>),
+    Tool(Tool),
     #[default]
     Miscellaneous,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolKind {
+    Pickaxe,
+    Axe,
+    Hammer,
+    Shears,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub struct Tool {
+    pub kind: ToolKind,
+    pub tier: u8,
+    /// Mining power on the blocks the tool is effective on. Everything else is mined like with an empty hand
+    pub mining_speed: f32,
+    /// One `BlockCategory::bit` per category the tool is effective on
+    #[serde(deserialize_with = "category_bits")]
+    pub effective: u8,
+    /// Blocks the tool can break before it's used up
+    pub durability: u32,
+    /// Blocks broken with it drop themselves instead of what their loot table would give
+    #[serde(default)]
+    pub silk_touch: bool,
+}
+
+impl Tool {
+    pub fn is_effective_on(&self, category: BlockCategory) -> bool {
+        self.effective & category.bit() != 0
+    }
+}
+
+fn category_bits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let categories = Vec::<BlockCategory>::deserialize(deserializer)?;
+    Ok(categories.into_iter().fold(0, |bits, category| bits | category.bit()))
+}
+
 #[derive(Clone, Copy, PartialEq, Debug, Default)]
 pub struct Item {
     pub id: u32,
@@ -26,55 +58,99 @@ pub struct Item {
     pub max_stack: u32,
 }
 
-#[derive(Resource)]
-pub struct ItemDatabase {
-    pub items: Vec<Value>,
-}
-
-impl ItemDatabase {
-    pub fn get_by_id(&self, id: u32) -> Item {
-        let item_data = self.items[id as usize].as_object().unwrap();
-        let mut item_type = ItemType::Miscellaneous;
-        
-        let max_stack = item_data.get("max_stack").unwrap().as_u64().unwrap() as u32;
-
-        if let Some(item_type_map) = item_data.get("item_type").unwrap().as_object() {
-            if let Some(val) = item_type_map.get("Block") {
-                item_type = ItemType::Block(val.as_object().unwrap().get("id").unwrap().as_u64().unwrap() as u32);
-            }
+impl Item {
+    pub fn tool(&self) -> Option<Tool> {
+        match self.item_type {
+            ItemType::Tool(tool) => Some(tool),
+            _ => None,
         }
-        else if let Some(item_type_str) = item_data.get("item_type").unwrap().as_str() {
-            match item_type_str {
-                "Tool" => { item_type = ItemType::Tool },
-                "Miscellaneous" => { item_type = ItemType::Miscellaneous },
-                _ => {}
-            }
+    }
+
+    /// How fast the item breaks `block` while held, compared to an empty hand
+    pub fn mining_power(&self, block: Block) -> f32 {
+        match self.tool() {
+            Some(tool) if tool.is_effective_on(block.category) => tool.mining_speed,
+            _ => 1.,
         }
+    }
+}
+
+/// How `item_type` is written in item_data.json, with blocks given by name
+#[derive(Deserialize)]
+pub enum ItemTypeData {
+    Block { id: String },
+    Bucket {
+        #[serde(default)]
+        liquid: Option<String>,
+    },
+    Tool(Tool),
+    Miscellaneous,
+}
+
+/// One entry of item_data.json
+#[derive(Deserialize)]
+pub struct ItemData {
+    /// Namespaced name like `rustaria:dirt`. The numeric id is assigned when the registry loads
+    pub id: String,
+    pub item_type: ItemTypeData,
+    /// Path inside the assets directory
+    pub texture: String,
+    pub max_stack: u32,
+}
 
+impl ItemData {
+    pub fn to_item(&self, id: u32, item_type: ItemType) -> Item {
         Item {
             id,
             item_type,
-            max_stack,
+            max_stack: self.max_stack,
         }
     }
+}
 
-    pub fn get_texture_by_id(&self, id: u32) -> String {
-        let item_data = self.items[id as usize].as_object().unwrap();
+/// Every item, indexed by id. Built once by the registry, see `registry::load_registries`
+#[derive(Resource)]
+pub struct ItemDatabase {
+    items: Vec<Item>,
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+    textures: Vec<String>,
+}
 
-        item_data.get("texture").unwrap().as_str().unwrap().to_string()
+impl ItemDatabase {
+    /// `items[i]` has id `i`, is called `names[i]` and looks like `textures[i]`
+    pub fn new(items: Vec<Item>, names: Vec<String>, textures: Vec<String>) -> Self {
+        let ids = names.iter().enumerate().map(|(id, name)| (name.clone(), id as u32)).collect();
+        Self { items, names, ids, textures }
     }
-}
 
-fn init_database(
-    mut commands: Commands,
-) {
-    let mut file = File::open("assets/item_data.json").unwrap();
+    pub fn get_by_id(&self, id: u32) -> Item {
+        self.items[id as usize]
+    }
+
+    /// Like `get_by_id`, but `None` for ids that aren't registered
+    pub fn get(&self, id: u32) -> Option<Item> {
+        self.items.get(id as usize).copied()
+    }
 
-    let mut string = String::new();
-    let _ = file.read_to_string(&mut string);
+    /// Names without a namespace are looked up in the default one, so `dirt` finds `rustaria:dirt`
+    pub fn get_by_name(&self, name: &str) -> Option<Item> {
+        self.ids.get(&qualify(name)).map(|id| self.items[*id as usize])
+    }
 
-    let map = serde_json::from_str::<serde_json::Map<String, Value>>(&string).unwrap();
-    let items: Vec<Value> = map.get("items").unwrap().as_array().unwrap().to_vec();
+    /// Names of every item, in id order
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
 
-    commands.insert_resource(ItemDatabase{items});
-}
\ No newline at end of file
+    /// Bucket item holding `liquid`, or the empty bucket for `None`
+    pub fn get_bucket(&self, liquid: Option<u32>) -> Option<Item> {
+        self.items.iter()
+            .find(|item| item.item_type == ItemType::Bucket(liquid))
+            .copied()
+    }
+
+    pub fn get_texture_by_id(&self, id: u32) -> String {
+        self.textures[id as usize].clone()
+    }
+}
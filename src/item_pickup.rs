@@ -4,6 +4,7 @@ use bevy_rapier2d::prelude::*;
 use crate::{
     inventory::{item::{Item, ItemDatabase}, Inventory},
     player::Player,
+    registry::RegistriesReloaded,
     BLOCK_SIZE_PX
 };
 
@@ -14,7 +15,7 @@ impl Plugin for ItemPickupPlugin {
         app.add_event::<SpawnItemPickup>();
 
         app
-            .add_systems(Update, spawn_item_pickup)
+            .add_systems(Update, (spawn_item_pickup, refresh_pickup_items))
             .add_systems(FixedUpdate, pull_to_player);
     }
 }
@@ -22,12 +23,14 @@ impl Plugin for ItemPickupPlugin {
 #[derive(Event)]
 pub struct SpawnItemPickup {
     pub item: Item,
+    pub amount: u32,
     pub position: Vec2,
 }
 
 #[derive(Component)]
 pub struct ItemPickup {
-    item: Item
+    item: Item,
+    amount: u32,
 }
 
 fn spawn_item_pickup(
@@ -49,7 +52,7 @@ fn spawn_item_pickup(
             Sprite::from_image(asset_server.load(item_database.get_texture_by_id(ev.item.id))),
             Collider::ball(BLOCK_SIZE_PX/2.),
             CollisionGroups::new(Group::GROUP_3, Group::GROUP_2),
-            ItemPickup { item: ev.item }
+            ItemPickup { item: ev.item, amount: ev.amount }
         ));     
     }
 }
@@ -57,12 +60,12 @@ fn spawn_item_pickup(
 fn pull_to_player(
     mut commands: Commands,
     mut q_player: Query<(&Transform, &mut Inventory), (With<Player>, Without<ItemPickup>)>,
-    mut q_pickup: Query<(Entity, &mut Velocity, &Transform, &ItemPickup), Without<Player>>,
+    mut q_pickup: Query<(Entity, &mut Velocity, &Transform, &mut ItemPickup), Without<Player>>,
     time: Res<Time>,
 ) {
     let Ok((player_transform, mut inventory)) = q_player.get_single_mut() else { return };
 
-    for (pickup_entity, mut pickup_velocity, pickup_transform, item_pickup) in q_pickup.iter_mut() {
+    for (pickup_entity, mut pickup_velocity, pickup_transform, mut item_pickup) in q_pickup.iter_mut() {
         if !inventory.has_room(item_pickup.item) { continue; };
 
         let distance = pickup_transform.translation.distance(player_transform.translation);
@@ -73,9 +76,24 @@ fn pull_to_player(
         }
 
         if distance < BLOCK_SIZE_PX {
-            inventory.add_item(item_pickup.item);
-            commands.entity(pickup_entity).despawn_recursive();
+            // whatever doesn't fit stays on the ground
+            item_pickup.amount = inventory.add_items(item_pickup.item, item_pickup.amount);
+            if item_pickup.amount == 0 {
+                commands.entity(pickup_entity).despawn_recursive();
+            }
             return;
         }
     }
-}
\ No newline at end of file
+}
+
+fn refresh_pickup_items(
+    mut ev_reloaded: EventReader<RegistriesReloaded>,
+    mut q_pickups: Query<&mut ItemPickup>,
+    item_database: Res<ItemDatabase>,
+) {
+    if ev_reloaded.read().count() == 0 { return; }
+
+    for mut pickup in q_pickups.iter_mut() {
+        pickup.item = item_database.get_by_id(pickup.item.id);
+    }
+}
@@ -1,4 +1,5 @@
 use bevy::{
+        asset::io::AssetSourceBuilder,
         prelude::*,
         window::WindowResolution
 };
@@ -26,14 +27,23 @@ use item_pickup::ItemPickupPlugin;
 mod ui;
 use ui::UiPlugin;
 
+mod save;
+use save::SavePlugin;
+
+mod registry;
+use registry::RegistryPlugin;
+
+mod pack;
+use pack::{PackPlugin, PACKS_DIR, PACKS_SOURCE};
+
 const CHUNK_WIDTH: usize = 32;
-const CHUNK_HEIGHT: usize = 256;
+const CHUNK_HEIGHT: usize = 32;
 const BLOCK_SIZE_PX: f32 = 16.;
 
-const SEED: &'static str = "I WANNA KMS";
-
 fn main() {
     App::new()
+        // pack textures load from `packs://<pack dir>/...`, has to be registered before the asset plugin
+        .register_asset_source(PACKS_SOURCE, AssetSourceBuilder::platform_default(PACKS_DIR, None))
         .add_plugins(DefaultPlugins
             .set(WindowPlugin {
                     primary_window: Some(Window {
@@ -48,6 +58,8 @@ fn main() {
                 .set(ImagePlugin::default_nearest()))
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
         // .add_plugins(RapierDebugRenderPlugin::default())
+        .add_plugins(PackPlugin)
+        .add_plugins(RegistryPlugin)
         .add_plugins(WorldPlugin)
         .add_plugins(CameraPlugin)
         .add_plugins(PlayerPlugin)
@@ -55,6 +67,7 @@ fn main() {
         .add_plugins(InventoryPlugin)
         .add_plugins(ItemPickupPlugin)
         .add_plugins(UiPlugin)
+        .add_plugins(SavePlugin)
         .run();
 }     
 
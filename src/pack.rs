@@ -0,0 +1,213 @@
+//! Content packs add to or override the base game data in `assets`.
+//!
+//! A pack is a directory in `packs` with a `pack.json` manifest, laid out like `assets`:
+//! `block_data.json`, `item_data.json`, `crafting_recipes_data.json`, `loot_tables.json`, `blocks.png`, `textures/`,
+//! `structures/`, `biome_data.json`, `tree_species.json` and `world_gen_preset.json`, all optional.
+//! Packs load after the base assets, each after the packs in its `load_after` and otherwise by id.
+//! A later pack replaces earlier entries with the same id, recipes for the same output, loot tables for the same block,
+//! biomes and tree species with the same name and structures with the same file name. The last world_gen_preset.json wins.
+//! Every blocks.png is kept, blocks pick their tile from the one of their own pack
+
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// Directory packs are found in, which is also registered as the `packs://` asset source
+pub const PACKS_DIR: &str = "packs";
+pub const PACKS_SOURCE: &str = "packs";
+
+const BASE_DIR: &str = "assets";
+const BASE_ID: &str = "rustaria";
+const MANIFEST_FILE: &str = "pack.json";
+
+pub struct PackPlugin;
+
+impl Plugin for PackPlugin {
+    fn build(&self, app: &mut App) {
+        // everything else loads from the packs at startup, so they have to be known before any system runs
+        app.insert_resource(ContentPacks::discover());
+    }
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct PackManifest {
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    /// Ids of packs this one has to load after, so it can override them
+    #[serde(default)]
+    pub load_after: Vec<String>,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+pub struct ContentPack {
+    pub id: String,
+    pub dir: PathBuf,
+    /// Prefix that turns a path inside the pack into an asset path, empty for the base assets
+    asset_prefix: String,
+}
+
+impl ContentPack {
+    /// Path of a data file inside the pack, if the pack has it
+    pub fn file(&self, name: &str) -> Option<PathBuf> {
+        let path = self.dir.join(name);
+        path.exists().then_some(path)
+    }
+
+    /// The base assets, which have to have every data file
+    pub fn is_base(&self) -> bool {
+        self.asset_prefix.is_empty()
+    }
+
+    /// Path the asset server loads a file of this pack from
+    pub fn asset_path(&self, path: &str) -> String {
+        format!("{}{path}", self.asset_prefix)
+    }
+}
+
+/// The base assets followed by every enabled pack, in load order
+#[derive(Resource)]
+pub struct ContentPacks(Vec<ContentPack>);
+
+impl ContentPacks {
+    pub fn iter(&self) -> impl Iterator<Item = &ContentPack> {
+        self.0.iter()
+    }
+
+    /// Every pack that has the data file `name`, with its path, in load order
+    pub fn files<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a ContentPack, PathBuf)> + 'a {
+        self.0.iter().filter_map(move |pack| pack.file(name).map(|path| (pack, path)))
+    }
+
+    /// Only the base assets, without looking in `packs`
+    pub fn base() -> Self {
+        Self(vec![ContentPack {
+            id: BASE_ID.to_string(),
+            dir: PathBuf::from(BASE_DIR),
+            asset_prefix: String::new(),
+        }])
+    }
+
+    pub fn discover() -> Self {
+
+        let mut manifests = vec![];
+        for entry in fs::read_dir(PACKS_DIR).into_iter().flatten().flatten() {
+            let dir = entry.path();
+            if !dir.is_dir() { continue; }
+
+            match read_manifest(&dir) {
+                Ok(manifest) if !manifest.enabled => info!("pack \"{}\" is disabled", manifest.id),
+                Ok(manifest) if manifest.id == BASE_ID || manifests.iter().any(|(other, _): &(PackManifest, _)| other.id == manifest.id) => {
+                    error!("{}: pack id \"{}\" is already taken, skipping it", dir.display(), manifest.id);
+                },
+                Ok(manifest) => manifests.push((manifest, dir)),
+                Err(err) => error!("{}: {err}, skipping it", dir.join(MANIFEST_FILE).display()),
+            }
+        }
+
+        let Self(mut packs) = Self::base();
+        for (manifest, dir) in load_order(manifests) {
+            let dir_name = dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let name = if manifest.name.is_empty() { &manifest.id } else { &manifest.name };
+            info!("loading pack \"{name}\" {} from {}", manifest.version, dir.display());
+
+            packs.push(ContentPack {
+                id: manifest.id,
+                asset_prefix: format!("{PACKS_SOURCE}://{dir_name}/"),
+                dir,
+            });
+        }
+
+        Self(packs)
+    }
+}
+
+fn read_manifest(dir: &Path) -> Result<PackManifest, String> {
+    let string = fs::read_to_string(dir.join(MANIFEST_FILE)).map_err(|err| format!("can't be read: {err}"))?;
+    serde_json::from_str(&string).map_err(|err| format!("isn't a valid manifest: {err}"))
+}
+
+/// Sorts packs so each comes after everything in its `load_after`, and by id where that leaves a choice.
+/// Missing dependencies are ignored, packs in a cycle go last
+fn load_order(manifests: Vec<(PackManifest, PathBuf)>) -> Vec<(PackManifest, PathBuf)> {
+    let ids: HashSet<String> = manifests.iter().map(|(manifest, _)| manifest.id.clone()).collect();
+    for (manifest, _) in manifests.iter() {
+        for dependency in manifest.load_after.iter().filter(|id| *id != BASE_ID && !ids.contains(*id)) {
+            warn!("pack \"{}\" loads after \"{dependency}\", which isn't there", manifest.id);
+        }
+    }
+
+    let mut remaining: HashMap<String, (PackManifest, PathBuf)> = manifests.into_iter()
+        .map(|(manifest, dir)| (manifest.id.clone(), (manifest, dir)))
+        .collect();
+    let mut ordered = vec![];
+
+    while !remaining.is_empty() {
+        let mut ready: Vec<String> = remaining.values()
+            .filter(|(manifest, _)| manifest.load_after.iter().all(|id| !remaining.contains_key(id)))
+            .map(|(manifest, _)| manifest.id.clone())
+            .collect();
+
+        if ready.is_empty() {
+            let mut cycle: Vec<String> = remaining.keys().cloned().collect();
+            cycle.sort();
+            error!("packs {cycle:?} load after each other, loading them by id");
+            ready = cycle;
+        }
+
+        ready.sort();
+        for id in ready {
+            ordered.extend(remaining.remove(&id));
+        }
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(id: &str, load_after: &[&str]) -> (PackManifest, PathBuf) {
+        let manifest = PackManifest {
+            id: id.to_string(),
+            name: String::new(),
+            version: String::new(),
+            load_after: load_after.iter().map(|id| id.to_string()).collect(),
+            enabled: true,
+        };
+        (manifest, PathBuf::from(PACKS_DIR).join(id))
+    }
+
+    fn ids(manifests: Vec<(PackManifest, PathBuf)>) -> Vec<String> {
+        load_order(manifests).into_iter().map(|(manifest, _)| manifest.id).collect()
+    }
+
+    #[test]
+    fn packs_load_after_their_dependencies() {
+        let order = ids(vec![manifest("a", &["b"]), manifest("b", &["c"]), manifest("c", &[BASE_ID])]);
+        assert_eq!(order, ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn packs_without_dependencies_load_by_id() {
+        let order = ids(vec![manifest("d", &[]), manifest("b", &[]), manifest("c", &["missing"]), manifest("a", &["d"])]);
+        // "a" has to wait for "d", the others are free from the start
+        assert_eq!(order, ["b", "c", "d", "a"]);
+    }
+
+    #[test]
+    fn packs_in_a_cycle_load_last_by_id() {
+        let order = ids(vec![manifest("z", &["y"]), manifest("y", &["z"]), manifest("m", &[]), manifest("x", &["z"])]);
+        // "x" only waits on the cycle, so it is loaded with it
+        assert_eq!(order, ["m", "x", "y", "z"]);
+    }
+}
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use crate::{inventory::Inventory, BLOCK_SIZE_PX, CHUNK_HEIGHT, CHUNK_WIDTH};
+use crate::{inventory::Inventory, BLOCK_SIZE_PX, CHUNK_WIDTH};
 
 mod movement;
 use movement::PlayerMovementPlugin;
@@ -12,6 +12,9 @@ use block_interaction::BlockInteractionPlugin;
 pub mod hotbar;
 use hotbar::HotbarPlugin;
 
+/// In blocks, somewhat above the base height of the terrain so the player drops onto the surface
+const SPAWN_HEIGHT: f32 = 160.;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
@@ -55,7 +58,7 @@ fn spawn_player(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>, mut ma
         Mesh2d(meshes.add(Rectangle::default())),
         MeshMaterial2d(materials.add(Color::WHITE)),
         Transform {
-            translation: Vec3::new( BLOCK_SIZE_PX * CHUNK_WIDTH as f32/2., BLOCK_SIZE_PX * CHUNK_HEIGHT as f32, 0.0),
+            translation: Vec3::new( BLOCK_SIZE_PX * CHUNK_WIDTH as f32/2., BLOCK_SIZE_PX * SPAWN_HEIGHT, 0.0),
             scale: Vec3::new(BLOCK_SIZE_PX, BLOCK_SIZE_PX*2., 1.),
             ..default()
         },
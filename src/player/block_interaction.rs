@@ -2,27 +2,35 @@ use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
 use crate::{
-    inventory::{item::ItemType, Inventory}, mouse_position::MousePosition, ui::mode_manager::UiState, world::{
-        chunk::block::{Block, BlockDatabase, BlockLayer},
+    inventory::{item::{ItemDatabase, ItemType}, Inventory, ItemSlot}, mouse_position::MousePosition, ui::mode_manager::UiState, world::{
+        chunk::block::{Block, BlockDatabase, BlockLayer, MAX_LIQUID_LEVEL},
         SetBlock,
         World
     }, BLOCK_SIZE_PX};
 
 use super::{hotbar::Hotbar, Player};
 
+/// Durability an empty hand breaks per second. A block takes `durability / (MINING_SPEED * mining_power)` seconds
+const MINING_SPEED: f32 = 4.;
+
+/// Tiles in textures/cracks.png, from a scratch to nearly broken
+const CRACK_STAGES: usize = 4;
+
 pub struct BlockInteractionPlugin;
 
 impl Plugin for BlockInteractionPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<SelectedBlock>();
+        app.init_resource::<MiningProgress>();
         
         app
-            .add_systems(OnEnter(UiState::InGame), spawn_selection_box)
+            .add_systems(OnEnter(UiState::InGame), (spawn_selection_box, spawn_crack_overlay))
             .add_systems(Update, (
                 toggle_selection_mode,
                 update_selected_position,
-                (move_selection_box, break_blocks, place_blocks)
-                    .run_if(in_state(UiState::InGame))
+                (move_selection_box, break_blocks, place_blocks, use_buckets)
+                    .run_if(in_state(UiState::InGame)),
+                update_crack_overlay.after(break_blocks).run_if(in_state(UiState::InGame)),
             ));
     }
 }
@@ -44,6 +52,25 @@ fn spawn_selection_box(
     ));
 }
 
+#[derive(Component)]
+struct CrackOverlay;
+
+fn spawn_crack_overlay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.spawn((
+        Sprite {
+            image: asset_server.load("textures/cracks.png"),
+            rect: Some(Rect::new(0., 0., BLOCK_SIZE_PX, BLOCK_SIZE_PX)),
+            ..default()
+        },
+        Visibility::Hidden,
+        CrackOverlay,
+        StateScoped(UiState::InGame)
+    ));
+}
+
 #[derive(PartialEq)]
 enum BlockSelectionMode {
     Free,
@@ -62,6 +89,16 @@ struct SelectedBlock {
     selection_mode: BlockSelectionMode,
 }
 
+/// Damage done so far to the block being mined. Starts over once another block or layer is targeted
+#[derive(Resource, Default)]
+struct MiningProgress {
+    position: Vec2,
+    layer: BlockLayer,
+    damage: f32,
+    /// Damage is only shown relative to this, 0 when nothing is being mined
+    durability: f32,
+}
+
 fn toggle_selection_mode(
     mut selected: ResMut<SelectedBlock>,
     keyboard: Res<ButtonInput<KeyCode>>,
@@ -107,33 +144,78 @@ fn move_selection_box(
     selection_transform.translation = (selected.position + Vec2::splat(BLOCK_SIZE_PX/2.)).extend(2.0);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn break_blocks(
+    time: Res<Time>,
     mouse_button: Res<ButtonInput<MouseButton>>,
     keyboard: Res<ButtonInput<KeyCode>>,
     selected: Res<SelectedBlock>,
+    mut progress: ResMut<MiningProgress>,
     mut ev_break_block: EventWriter<SetBlock>,
+    mut inventory: Single<&mut Inventory, With<Player>>,
+    hotbar: Res<Hotbar>,
     world: Res<World>,
 ) {
-    if mouse_button.pressed(MouseButton::Left) {
+    if !mouse_button.pressed(MouseButton::Left) {
+        *progress = MiningProgress::default();
+        return;
+    }
 
-        let layer;
-        if keyboard.pressed(KeyCode::ShiftLeft) {
-            layer = BlockLayer::Background;
-        }
-        else {
-            layer = BlockLayer::Foreground;
-        }
+    let layer = if keyboard.pressed(KeyCode::ShiftLeft) { BlockLayer::Background } else { BlockLayer::Foreground };
+
+    if progress.position != selected.position || progress.layer != layer {
+        *progress = MiningProgress { position: selected.position, layer, ..default() };
+    }
+
+    let Some(block) = world.get_block(selected.position.x, selected.position.y, layer) else { return; };
+    if block.id == 0 || block.is_liquid {
+        progress.durability = 0.;
+        return;
+    };
+
+    let held_item = inventory.items[hotbar.selected_slot].item;
+    let mining_power = held_item.map_or(1., |item| item.mining_power(block));
+
+    progress.durability = block.durability as f32;
+    progress.damage += MINING_SPEED * mining_power * time.delta_secs();
+    if progress.damage < progress.durability { return; }
 
-        let Some(block) = world.get_block(selected.position.x, selected.position.y, layer) else { return; };
-        if block.id == 0 { return; };
+    let tool = held_item.and_then(|item| item.tool());
+    ev_break_block.send(SetBlock {
+        block: Block::AIR,
+        position: selected.position,
+        layer,
+        can_overwrite: true,
+        tool,
+    });
 
-        ev_break_block.send(SetBlock {
-            block: Block::AIR,
-            position: selected.position,
-            layer,
-            can_overwrite: true,
-        });
+    if tool.is_some_and(|tool| tool.is_effective_on(block.category)) {
+        inventory.wear_tool_in_slot(hotbar.selected_slot);
     }
+
+    // the block behind or the next one in the column starts from scratch
+    progress.damage = 0.;
+    progress.durability = 0.;
+}
+
+fn update_crack_overlay(
+    progress: Res<MiningProgress>,
+    overlay: Single<(&mut Sprite, &mut Transform, &mut Visibility), With<CrackOverlay>>,
+) {
+    let (mut sprite, mut transform, mut visibility) = overlay.into_inner();
+
+    if progress.durability <= 0. || progress.damage <= 0. {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+
+    let stage = ((progress.damage / progress.durability * CRACK_STAGES as f32) as usize).min(CRACK_STAGES - 1);
+    let left = stage as f32 * BLOCK_SIZE_PX;
+
+    sprite.rect = Some(Rect::new(left, 0., left + BLOCK_SIZE_PX, BLOCK_SIZE_PX));
+    // above the selection box
+    transform.translation = (progress.position + Vec2::splat(BLOCK_SIZE_PX/2.)).extend(2.1);
+    *visibility = Visibility::Visible;
 }
 
 fn place_blocks(
@@ -174,10 +256,48 @@ fn place_blocks(
                         position: selected.position,
                         layer,
                         can_overwrite: false,
+                        tool: None,
                     });
                 }
             },
             _ => return
         }
     }
-}
\ No newline at end of file
+}
+
+#[allow(clippy::too_many_arguments)]
+fn use_buckets(
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    selected: Res<SelectedBlock>,
+    mut ev_set_block: EventWriter<SetBlock>,
+    block_database: Res<BlockDatabase>,
+    item_database: Res<ItemDatabase>,
+    mut inventory: Single<&mut Inventory, With<Player>>,
+    hotbar: Res<Hotbar>,
+    world: Res<World>,
+) {
+    if !mouse_button.just_pressed(MouseButton::Right) { return; }
+
+    let Some(selected_item) = inventory.items[hotbar.selected_slot].item else { return };
+    let ItemType::Bucket(liquid) = selected_item.item_type else { return };
+
+    let Some(block) = world.get_block(selected.position.x, selected.position.y, BlockLayer::Foreground) else { return; };
+
+    let (new_block, new_liquid) = match liquid {
+        // only full cells, so a bucket can't be filled from a thin layer and make liquid out of nothing
+        None if block.is_liquid && block.level == MAX_LIQUID_LEVEL => (Block::AIR, Some(block.id)),
+        Some(id) if block.id == 0 => (block_database.get_by_id(id), None),
+        _ => return,
+    };
+
+    let Some(new_bucket) = item_database.get_bucket(new_liquid) else { return };
+    inventory.items[hotbar.selected_slot] = ItemSlot { item: Some(new_bucket), amount: 1, wear: 0 };
+
+    ev_set_block.send(SetBlock {
+        block: new_block,
+        position: selected.position,
+        layer: BlockLayer::Foreground,
+        can_overwrite: true,
+        tool: None,
+    });
+}
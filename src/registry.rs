@@ -0,0 +1,545 @@
+use std::{collections::HashMap, fmt, fs, path::{Path, PathBuf}};
+
+use bevy::{asset::{io::Reader, AssetLoader, LoadContext}, prelude::*};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::{
+    inventory::item::{ItemData, ItemDatabase, ItemType, ItemTypeData},
+    pack::{ContentPack, ContentPacks},
+    world::chunk::{atlas::{BlockAtlas, ATLAS_FILE, ATLAS_TILES}, block::{BlockData, BlockDatabase}}
+};
+
+const BLOCKS_FILE: &str = "block_data.json";
+const ITEMS_FILE: &str = "item_data.json";
+pub const RECIPES_FILE: &str = "crafting_recipes_data.json";
+pub const LOOT_TABLES_FILE: &str = "loot_tables.json";
+
+/// Loads and checks block and item data from every content pack before anything uses it. Nothing is inserted if any of it is wrong,
+/// every problem is logged instead and the app exits.
+///
+/// The data is rebuilt when one of the files changes, which only gets noticed with the `dev` feature watching them.
+/// A broken change, or one that removes or reorders ids, is logged and the previous data kept
+pub struct RegistryPlugin;
+
+impl Plugin for RegistryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<DataFile>();
+        app.init_asset_loader::<DataFileLoader>();
+        app.add_event::<RegistriesReloaded>();
+        app.add_systems(PreStartup, load_registries);
+        app.add_systems(Update, reload_registries);
+    }
+}
+
+/// Sent once block and item data was rebuilt from changed files. Ids stay the same,
+/// so anything holding a copy of a block or an item only has to fetch it again
+#[derive(Event)]
+pub struct RegistriesReloaded;
+
+/// Contents of a data file, held by the asset server so it notices when the file changes
+#[derive(Asset, TypePath)]
+pub struct DataFile(String);
+
+#[derive(Default)]
+struct DataFileLoader;
+
+impl AssetLoader for DataFileLoader {
+    type Asset = DataFile;
+    type Settings = ();
+    type Error = std::io::Error;
+
+    async fn load(&self, reader: &mut dyn Reader, _settings: &(), _load_context: &mut LoadContext<'_>) -> Result<DataFile, Self::Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes).await?;
+        String::from_utf8(bytes).map(DataFile).map_err(std::io::Error::other)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["json"]
+    }
+}
+
+/// Block, item, recipe and loot table files of every pack, by path on disk
+#[derive(Resource)]
+pub struct RegistryFiles(HashMap<PathBuf, Handle<DataFile>>);
+
+impl RegistryFiles {
+    /// Contents of a data file, from the asset server once it has loaded it and from disk until then
+    pub fn read(&self, data_files: &Assets<DataFile>, path: &Path) -> Result<String, String> {
+        match self.0.get(path).and_then(|handle| data_files.get(handle)) {
+            Some(file) => Ok(file.0.clone()),
+            None => fs::read_to_string(path).map_err(|err| err.to_string()),
+        }
+    }
+
+    /// Whether an asset event is about one of the files
+    pub fn contains(&self, id: AssetId<DataFile>) -> bool {
+        self.0.values().any(|handle| handle.id() == id)
+    }
+}
+
+/// Something wrong with one entry of a data file
+pub struct RegistryError {
+    pub file: String,
+    /// Which entry, like `blocks[3] "stone"`, or empty when the whole file is wrong
+    pub entry: String,
+    pub message: String,
+}
+
+impl RegistryError {
+    pub fn new(file: &str, entry: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { file: file.to_string(), entry: entry.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.entry.is_empty() {
+            write!(f, "{}: {}", self.file, self.message)
+        }
+        else {
+            write!(f, "{}: {}: {}", self.file, self.entry, self.message)
+        }
+    }
+}
+
+/// `key[index]`, followed by the entry's id or name if it has one
+fn entry_label(key: &str, index: usize, value: &Value) -> String {
+    match value.get("id").or_else(|| value.get("name")).and_then(|id| id.as_str()) {
+        Some(name) => format!("{key}[{index}] \"{name}\""),
+        None => format!("{key}[{index}]"),
+    }
+}
+
+/// One entry of a data file, and where it came from
+struct Entry<'a, T> {
+    pack: &'a ContentPack,
+    file: String,
+    /// Like `blocks[3] "rustaria:stone"`
+    label: String,
+    data: T,
+}
+
+impl<T> Entry<'_, T> {
+    fn error(&self, message: impl Into<String>) -> RegistryError {
+        RegistryError::new(&self.file, self.label.as_str(), message)
+    }
+}
+
+/// Deserializes every entry of the `key` array on its own, so one bad entry doesn't hide the others
+pub fn read_entries<T: DeserializeOwned>(
+    path: &Path,
+    key: &str,
+    read: &dyn Fn(&Path) -> Result<String, String>,
+) -> (Vec<(String, T)>, Vec<RegistryError>) {
+    let file = path.to_string_lossy();
+
+    let string = match read(path) {
+        Ok(string) => string,
+        Err(err) => return (vec![], vec![RegistryError::new(&file, "", format!("can't be read: {err}"))]),
+    };
+
+    let map = match serde_json::from_str::<Map<String, Value>>(&string) {
+        Ok(map) => map,
+        Err(err) => return (vec![], vec![RegistryError::new(&file, "", format!("isn't valid json: {err}"))]),
+    };
+
+    let Some(array) = map.get(key).and_then(|entries| entries.as_array()) else {
+        return (vec![], vec![RegistryError::new(&file, "", format!("has no \"{key}\" array"))]);
+    };
+
+    let mut entries = vec![];
+    let mut errors = vec![];
+
+    for (index, value) in array.iter().enumerate() {
+        let label = entry_label(key, index, value);
+        match T::deserialize(value) {
+            Ok(data) => entries.push((label, data)),
+            Err(err) => errors.push(RegistryError::new(&file, label, err.to_string())),
+        }
+    }
+
+    (entries, errors)
+}
+
+/// Entries of `file` in `pack`, none if any of them is broken
+fn read_pack_entries<'a, T: DeserializeOwned>(
+    pack: &'a ContentPack,
+    file: &str,
+    key: &str,
+    read: &dyn Fn(&Path) -> Result<String, String>,
+) -> Result<Vec<Entry<'a, T>>, Vec<RegistryError>> {
+    let path = pack.dir.join(file);
+    let (entries, errors) = read_entries::<T>(&path, key, read);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let file = path.to_string_lossy().into_owned();
+    Ok(entries.into_iter().map(|(label, data)| Entry { pack, file: file.clone(), label, data }).collect())
+}
+
+/// Entries of `file` from every pack that has it, in load order. An entry with the id of one from an earlier pack
+/// takes its place, so numeric ids don't shift when a pack changes a block
+fn read_packs<'a, T: DeserializeOwned>(
+    packs: &'a ContentPacks,
+    file: &str,
+    key: &str,
+    id: impl Fn(&T) -> &str,
+    read: &dyn Fn(&Path) -> Result<String, String>,
+    errors: &mut Vec<RegistryError>,
+) -> Vec<Entry<'a, T>> {
+    let mut entries: Vec<Entry<T>> = vec![];
+
+    for pack in packs.iter().filter(|pack| pack.is_base() || pack.file(file).is_some()) {
+        let pack_entries = read_pack_entries::<T>(pack, file, key, read).unwrap_or_else(|err| {
+            errors.extend(err);
+            vec![]
+        });
+
+        for entry in pack_entries {
+            // ids repeated within one file are left for `assign_ids` to report
+            match entries.iter().position(|other| id(&other.data) == id(&entry.data) && other.file != entry.file) {
+                Some(index) => {
+                    info!("pack \"{}\" overrides {}", pack.id, id(&entry.data));
+                    entries[index] = entry;
+                },
+                None => entries.push(entry),
+            }
+        }
+    }
+
+    entries
+}
+
+/// Namespace of names written without one
+pub const DEFAULT_NAMESPACE: &str = "rustaria";
+
+/// Name of the block that fills empty cells, always numeric id 0
+pub const AIR: &str = "rustaria:air";
+
+/// Puts names without a namespace into the default one
+pub fn qualify(name: &str) -> String {
+    if name.contains(':') { name.to_string() } else { format!("{DEFAULT_NAMESPACE}:{name}") }
+}
+
+/// Checks every entry has a namespaced id of its own, and returns the numeric id for each name, in file order
+fn assign_ids<T>(entries: &[Entry<T>], id: impl Fn(&T) -> &str) -> (HashMap<String, u32>, Vec<RegistryError>) {
+    let mut ids = HashMap::new();
+    let mut errors = vec![];
+
+    for entry in entries.iter() {
+        let name = id(&entry.data);
+
+        if name.split(':').filter(|part| !part.is_empty()).count() != 2 {
+            errors.push(entry.error(format!("id \"{name}\" isn't namespaced like \"{DEFAULT_NAMESPACE}:{name}\"")));
+            continue;
+        }
+        if ids.contains_key(name) {
+            errors.push(entry.error(format!("id \"{name}\" is used by an earlier entry")));
+            continue;
+        }
+
+        ids.insert(name.to_string(), ids.len() as u32);
+    }
+
+    (ids, errors)
+}
+
+/// Tile of a block in the `BlockAtlas`, from its place in the blocks.png of its own pack
+fn block_tile(packs: &ContentPacks, block: &Entry<BlockData>) -> Result<u32, RegistryError> {
+    match (block.data.tile, BlockAtlas::page(packs, block.pack)) {
+        (Some([column, row]), Some(page)) if column < ATLAS_TILES && row < ATLAS_TILES => Ok(BlockAtlas::tile(page, column, row)),
+        (Some(tile), Some(_)) => Err(block.error(format!("tile {tile:?} is outside the {ATLAS_TILES}x{ATLAS_TILES} tiles of {ATLAS_FILE}"))),
+        (Some(_), None) => Err(block.error(format!("has a tile, but its pack has no {ATLAS_FILE}"))),
+        (None, _) if block.data.id == AIR => Ok(0),
+        (None, _) => Err(block.error("has no tile")),
+    }
+}
+
+/// Reads and checks the block and item data of every pack, `read` giving the contents of a file
+pub fn build_registries(
+    packs: &ContentPacks,
+    read: &dyn Fn(&Path) -> Result<String, String>,
+) -> Result<(BlockDatabase, ItemDatabase), Vec<RegistryError>> {
+    let mut errors = vec![];
+
+    let mut blocks = read_packs::<BlockData>(packs, BLOCKS_FILE, "blocks", |block| &block.id, read, &mut errors);
+    let items = read_packs::<ItemData>(packs, ITEMS_FILE, "items", |item| &item.id, read, &mut errors);
+
+    // air is id 0 whatever its place in the file, empty cells are all zeroes
+    match blocks.iter().position(|block| block.data.id == AIR) {
+        Some(index) => blocks[..=index].rotate_right(1),
+        None => errors.push(RegistryError::new(BLOCKS_FILE, "", format!("has no \"{AIR}\" block"))),
+    }
+
+    let (block_ids, block_errors) = assign_ids(&blocks, |block| &block.id);
+    let (item_ids, item_errors) = assign_ids(&items, |item| &item.id);
+    errors.extend(block_errors);
+    errors.extend(item_errors);
+
+    let mut resolve = |error: &dyn Fn(String) -> RegistryError, ids: &HashMap<String, u32>, kind: &str, name: &str| {
+        ids.get(&qualify(name)).copied().unwrap_or_else(|| {
+            errors.push(error(format!("{kind} \"{name}\" doesn't exist")));
+            0
+        })
+    };
+
+    let mut tile_errors = vec![];
+    let mut block_database = vec![];
+    for block in blocks.iter() {
+        let error = |message| block.error(message);
+        let drop_item = block.data.drop_item.as_ref().map(|item| resolve(&error, &item_ids, "drop_item", item));
+        let tile = block_tile(packs, block).unwrap_or_else(|err| {
+            tile_errors.push(err);
+            0
+        });
+        block_database.push(block.data.to_block(block_database.len() as u32, tile, drop_item));
+    }
+
+    let mut item_database = vec![];
+    for item in items.iter() {
+        let error = |message| item.error(message);
+        let item_type = match &item.data.item_type {
+            ItemTypeData::Block { id } => ItemType::Block(resolve(&error, &block_ids, "block", id)),
+            ItemTypeData::Bucket { liquid } => ItemType::Bucket(liquid.as_ref().map(|liquid| {
+                resolve(&error, &block_ids, "liquid", liquid)
+            })),
+            ItemTypeData::Tool(tool) => ItemType::Tool(*tool),
+            ItemTypeData::Miscellaneous => ItemType::Miscellaneous,
+        };
+        item_database.push(item.data.to_item(item_database.len() as u32, item_type));
+    }
+
+    errors.extend(tile_errors);
+
+    for (item, built) in items.iter().zip(item_database.iter()) {
+        if let ItemType::Bucket(Some(liquid)) = built.item_type {
+            if block_database.get(liquid as usize).is_some_and(|block| !block.is_liquid) {
+                errors.push(item.error("holds a block that isn't a liquid"));
+            }
+        }
+
+        // textures are looked up in the pack that added the item
+        if !item.pack.dir.join(&item.data.texture).is_file() {
+            errors.push(item.error(format!("texture {} doesn't exist", item.data.texture)));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let (block_names, tree_species) = blocks.into_iter()
+        .map(|block| (block.data.id, block.data.tree_species))
+        .unzip();
+    let (item_names, textures) = items.into_iter()
+        .map(|item| (item.data.id, item.pack.asset_path(&item.data.texture)))
+        .unzip();
+
+    Ok((BlockDatabase::new(block_database, block_names, tree_species), ItemDatabase::new(item_database, item_names, textures)))
+}
+
+fn load_registries(
+    mut commands: Commands,
+    mut ev_exit: EventWriter<AppExit>,
+    mut images: ResMut<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+    packs: Res<ContentPacks>,
+) {
+    let mut files = HashMap::new();
+    for pack in packs.iter() {
+        for file in [BLOCKS_FILE, ITEMS_FILE, RECIPES_FILE, LOOT_TABLES_FILE] {
+            if let Some(path) = pack.file(file) {
+                files.insert(path, asset_server.load(pack.asset_path(file)));
+            }
+        }
+    }
+    commands.insert_resource(RegistryFiles(files));
+
+    // the first build can't wait for the asset server, everything after this needs the databases
+    let registries = build_registries(&packs, &|path| fs::read_to_string(path).map_err(|err| err.to_string()));
+    let atlas = BlockAtlas::build(&packs, &mut images);
+
+    match (registries, atlas) {
+        (Ok((block_database, item_database)), Ok(atlas)) => {
+            commands.insert_resource(block_database);
+            commands.insert_resource(item_database);
+            commands.insert_resource(atlas);
+        },
+        (registries, atlas) => {
+            let errors: Vec<RegistryError> = registries.err().into_iter().chain(atlas.err()).flatten().collect();
+            for err in errors.iter() {
+                error!("{err}");
+            }
+            error!("block and item data has {} problems, exiting", errors.len());
+            ev_exit.send(AppExit::error());
+        },
+    }
+}
+
+/// Swaps in rebuilt databases if every name the running game knows keeps its id. Names can only be added at the end,
+/// anything else would need every chunk, inventory and save remapped, so the running databases are kept instead
+fn replace_keeping_ids(
+    block_database: &mut BlockDatabase,
+    item_database: &mut ItemDatabase,
+    (new_blocks, new_items): (BlockDatabase, ItemDatabase),
+) -> Result<(), String> {
+    let keeps_ids = |old: &[String], new: &[String]| new.starts_with(old);
+    if !keeps_ids(block_database.names(), new_blocks.names()) || !keeps_ids(item_database.names(), new_items.names()) {
+        return Err("blocks or items were removed, renamed or reordered".to_string());
+    }
+
+    *block_database = new_blocks;
+    *item_database = new_items;
+    Ok(())
+}
+
+fn reload_registries(
+    mut ev_asset: EventReader<AssetEvent<DataFile>>,
+    mut ev_reloaded: EventWriter<RegistriesReloaded>,
+    mut block_database: ResMut<BlockDatabase>,
+    mut item_database: ResMut<ItemDatabase>,
+    files: Res<RegistryFiles>,
+    data_files: Res<Assets<DataFile>>,
+    packs: Res<ContentPacks>,
+) {
+    let mut modified = false;
+    for ev in ev_asset.read() {
+        if let AssetEvent::Modified { id } = ev {
+            modified |= files.contains(*id);
+        }
+    }
+    if !modified { return; }
+
+    let databases = match build_registries(&packs, &|path| files.read(&data_files, path)) {
+        Ok(databases) => databases,
+        Err(errors) => {
+            for err in errors.iter() {
+                error!("{err}");
+            }
+            error!("block and item data has {} problems, keeping the previous data", errors.len());
+            return;
+        },
+    };
+
+    if let Err(err) = replace_keeping_ids(&mut block_database, &mut item_database, databases) {
+        error!("{err}, restart to use the changed data");
+        return;
+    }
+
+    info!("reloaded block and item data");
+    ev_reloaded.send(RegistriesReloaded);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inventory::item::Item, world::chunk::block::Block};
+
+    const DIRT: &str = r#"{ "id": "rustaria:dirt", "tile": [1, 0], "is_solid": true, "durability": 2, "drop_item": "rustaria:dirt", "light_emission": 0 }"#;
+    const DIRT_ITEM: &str = r#"{ "id": "rustaria:dirt", "item_type": {"Block": { "id": "rustaria:dirt" }}, "texture": "textures/items/dirt_block.png", "max_stack": 99 }"#;
+
+    /// Every problem `build_registries` finds with the base pack holding `blocks` after air, and `items`
+    fn errors(blocks: &[&str], items: &[&str]) -> Vec<String> {
+        let blocks = format!(r#"{{ "blocks": [{{ "id": "{AIR}", "is_solid": false, "durability": 0, "light_emission": 0 }}, {}] }}"#, blocks.join(", "));
+        let items = format!(r#"{{ "items": [{}] }}"#, items.join(", "));
+
+        let read = |path: &Path| match path.file_name().and_then(|name| name.to_str()) {
+            Some(BLOCKS_FILE) => Ok(blocks.clone()),
+            Some(ITEMS_FILE) => Ok(items.clone()),
+            _ => Err("isn't there".to_string()),
+        };
+
+        match build_registries(&ContentPacks::base(), &read) {
+            Ok(_) => vec![],
+            Err(errors) => errors.iter().map(|err| err.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_data_has_no_errors() {
+        assert_eq!(errors(&[DIRT], &[DIRT_ITEM]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn duplicate_id_is_reported() {
+        assert_eq!(errors(&[DIRT, DIRT], &[DIRT_ITEM]), [
+            r#"assets/block_data.json: blocks[2] "rustaria:dirt": id "rustaria:dirt" is used by an earlier entry"#,
+        ]);
+    }
+
+    #[test]
+    fn missing_id_is_reported() {
+        let item = r#"{ "item_type": "Miscellaneous", "texture": "textures/items/flint.png", "max_stack": 99 }"#;
+        // the whole file is dropped for it, so the block's drop_item dangles too
+        let errors = errors(&[DIRT], &[DIRT_ITEM, item]);
+        assert!(errors[0].starts_with("assets/item_data.json: items[1]: missing field `id`"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn dangling_drop_item_is_reported() {
+        let block = DIRT.replace(r#""drop_item": "rustaria:dirt""#, r#""drop_item": "rustaria:mud""#);
+        assert_eq!(errors(&[&block], &[DIRT_ITEM]), [
+            r#"assets/block_data.json: blocks[1] "rustaria:dirt": drop_item "rustaria:mud" doesn't exist"#,
+        ]);
+    }
+
+    #[test]
+    fn dangling_block_item_is_reported() {
+        let item = DIRT_ITEM.replace(r#"{ "id": "rustaria:dirt" }"#, r#"{ "id": "rustaria:mud" }"#);
+        assert_eq!(errors(&[DIRT], &[&item]), [
+            r#"assets/item_data.json: items[0] "rustaria:dirt": block "rustaria:mud" doesn't exist"#,
+        ]);
+    }
+
+    #[test]
+    fn missing_texture_is_reported() {
+        let item = DIRT_ITEM.replace("dirt_block.png", "mud_block.png");
+        assert_eq!(errors(&[DIRT], &[&item]), [
+            r#"assets/item_data.json: items[0] "rustaria:dirt": texture textures/items/mud_block.png doesn't exist"#,
+        ]);
+    }
+
+    #[test]
+    fn missing_tile_is_reported() {
+        let block = DIRT.replace(r#""tile": [1, 0], "#, "");
+        assert_eq!(errors(&[&block], &[DIRT_ITEM]), [
+            r#"assets/block_data.json: blocks[1] "rustaria:dirt": has no tile"#,
+        ]);
+    }
+
+    /// Databases with blocks and items of these names, in id order
+    fn databases(blocks: &[&str], items: &[&str]) -> (BlockDatabase, ItemDatabase) {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        (
+            BlockDatabase::new(vec![Block::AIR; blocks.len()], names(blocks), vec![None; blocks.len()]),
+            ItemDatabase::new(vec![Item::default(); items.len()], names(items), vec![String::new(); items.len()]),
+        )
+    }
+
+    #[test]
+    fn names_added_at_the_end_are_swapped_in() {
+        let (mut blocks, mut items) = databases(&[AIR, "rustaria:dirt"], &["rustaria:dirt"]);
+
+        let reloaded = databases(&[AIR, "rustaria:dirt", "rustaria:mud"], &["rustaria:dirt", "rustaria:mud"]);
+        assert!(replace_keeping_ids(&mut blocks, &mut items, reloaded).is_ok());
+
+        assert_eq!(blocks.names(), [AIR, "rustaria:dirt", "rustaria:mud"]);
+        assert_eq!(items.names(), ["rustaria:dirt", "rustaria:mud"]);
+    }
+
+    #[test]
+    fn changed_ids_keep_the_running_data() {
+        let (mut blocks, mut items) = databases(&[AIR, "rustaria:dirt", "rustaria:mud"], &["rustaria:dirt"]);
+
+        let reordered = databases(&[AIR, "rustaria:mud", "rustaria:dirt"], &["rustaria:dirt"]);
+        assert!(replace_keeping_ids(&mut blocks, &mut items, reordered).is_err());
+        let removed = databases(&[AIR, "rustaria:dirt", "rustaria:mud"], &[]);
+        assert!(replace_keeping_ids(&mut blocks, &mut items, removed).is_err());
+
+        assert_eq!(blocks.names(), [AIR, "rustaria:dirt", "rustaria:mud"]);
+        assert_eq!(items.names(), ["rustaria:dirt"]);
+    }
+}
@@ -0,0 +1,497 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    inventory::{item::ItemDatabase, Inventory, ItemSlot},
+    player::Player,
+    registry::RegistriesReloaded,
+    world::{
+        chunk::{block::{Block, BlockDatabase}, Chunk},
+        clock::{WorldClock, START_TIME},
+        seed::WorldSeed,
+        World
+    },
+    CHUNK_HEIGHT, CHUNK_WIDTH
+};
+
+const SAVE_DIR: &str = "saves";
+const SAVE_PATH: &str = "saves/world.json";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveWorld>();
+
+        app
+            .add_systems(PreStartup, load_world_save)
+            .add_systems(PostStartup, (remap_palettes, load_player.after(remap_palettes)))
+            .add_systems(Update, extend_palettes)
+            .add_systems(Last, (
+                request_save,
+                // a new world isn't saved before the dialog gave it a seed
+                save_world.after(request_save).run_if(resource_exists::<WorldSeed>)
+            ));
+    }
+}
+
+/// One layer of a chunk as run-length encoded block ids, column by column
+type EncodedLayer = Vec<(u32, u32)>;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChunkSave {
+    data: EncodedLayer,
+    background_data: EncodedLayer,
+    /// Fill levels of the foreground liquids, encoded like the layers
+    #[serde(default)]
+    liquid_levels: EncodedLayer,
+}
+
+impl ChunkSave {
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        Self {
+            data: encode_layer(&chunk.data, |block| block.id),
+            background_data: encode_layer(&chunk.background_data, |block| block.id),
+            liquid_levels: encode_layer(&chunk.data, |block| block.level as u32),
+        }
+    }
+
+    /// Swaps every block id for `ids[id]`
+    fn remap(&mut self, ids: &[u32]) {
+        for (id, _) in self.data.iter_mut().chain(self.background_data.iter_mut()) {
+            *id = ids.get(*id as usize).copied().unwrap_or(0);
+        }
+    }
+
+    pub fn restore(&self, position: (i32, i32), block_database: &BlockDatabase) -> Chunk {
+        let mut chunk = Chunk::new(position);
+        decode_layer(&self.data, &mut chunk.data, block_database);
+        decode_layer(&self.background_data, &mut chunk.background_data, block_database);
+        decode_levels(&self.liquid_levels, &mut chunk.data);
+        chunk
+    }
+}
+
+fn encode_layer(layer: &[[Block; CHUNK_HEIGHT]; CHUNK_WIDTH], value: impl Fn(&Block) -> u32) -> EncodedLayer {
+    let mut runs: EncodedLayer = vec![];
+
+    for block in layer.iter().flatten() {
+        let value = value(block);
+        match runs.last_mut() {
+            Some((last, count)) if *last == value => *count += 1,
+            _ => runs.push((value, 1)),
+        }
+    }
+
+    runs
+}
+
+fn decode_layer(runs: &EncodedLayer, layer: &mut [[Block; CHUNK_HEIGHT]; CHUNK_WIDTH], block_database: &BlockDatabase) {
+    let mut index = 0;
+
+    for (id, count) in runs.iter() {
+        // blocks a removed pack added come back as air
+        let block = block_database.get(*id).unwrap_or(Block::AIR);
+        for _ in 0..*count {
+            if index >= CHUNK_WIDTH * CHUNK_HEIGHT { return; }
+            layer[index / CHUNK_HEIGHT][index % CHUNK_HEIGHT] = block;
+            index += 1;
+        }
+    }
+}
+
+/// Saves without levels keep the full liquids that `decode_layer` put in
+fn decode_levels(runs: &EncodedLayer, layer: &mut [[Block; CHUNK_HEIGHT]; CHUNK_WIDTH]) {
+    let mut index = 0;
+
+    for (level, count) in runs.iter() {
+        for _ in 0..*count {
+            if index >= CHUNK_WIDTH * CHUNK_HEIGHT { return; }
+            let block = &mut layer[index / CHUNK_HEIGHT][index % CHUNK_HEIGHT];
+            if block.is_liquid {
+                block.level = *level as u8;
+            }
+            index += 1;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PlayerSave {
+    translation: [f32; 3],
+    /// (item id, amount) for each inventory slot
+    items: Vec<Option<(u32, u32)>>,
+    /// Tool wear of each inventory slot, missing in saves from before tools wore down
+    #[serde(default)]
+    wear: Vec<u32>,
+}
+
+/// Everything that is written to disk. Chunks are only stored when they differ from fresh generation
+#[derive(Resource, Serialize, Deserialize, Clone)]
+pub struct WorldSave {
+    pub seed: String,
+    #[serde(with = "chunk_map")]
+    pub chunks: HashMap<(i32, i32), ChunkSave>,
+    pub player: Option<PlayerSave>,
+    /// Name of every block id used in the save, so the ids still mean the same blocks once the data files change.
+    /// Saves from before palettes use the ids of the current files
+    #[serde(default)]
+    pub block_palette: Vec<String>,
+    /// Same for item ids
+    #[serde(default)]
+    pub item_palette: Vec<String>,
+    #[serde(default = "start_time")]
+    pub time_of_day: f32,
+}
+
+fn start_time() -> f32 {
+    START_TIME
+}
+
+impl WorldSave {
+    pub fn new(seed: &WorldSeed) -> Self {
+        Self {
+            seed: seed.0.clone(),
+            chunks: HashMap::new(),
+            player: None,
+            block_palette: vec![],
+            item_palette: vec![],
+            time_of_day: START_TIME,
+        }
+    }
+
+    pub fn get_chunk(&self, position: (i32, i32)) -> Option<&ChunkSave> {
+        self.chunks.get(&position)
+    }
+}
+
+/// JSON object keys can only be strings, so chunks are written as a list of `[position, chunk]` pairs
+mod chunk_map {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::ChunkSave;
+
+    pub fn serialize<S: Serializer>(chunks: &HashMap<(i32, i32), ChunkSave>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(chunks.iter())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<(i32, i32), ChunkSave>, D::Error> {
+        let chunks = Vec::<((i32, i32), ChunkSave)>::deserialize(deserializer)?;
+        Ok(chunks.into_iter().collect())
+    }
+}
+
+/// File the world is read from and written to
+#[derive(Resource)]
+pub struct SaveFile {
+    pub path: PathBuf,
+    /// Off when a file that couldn't be read is still in the way, so it's never overwritten
+    writable: bool,
+}
+
+/// Save of a world that isn't the one in `SAVE_PATH`. Seeds with the same hash generate the same terrain,
+/// so they can share it
+fn seed_save_path(seed: &WorldSeed) -> PathBuf {
+    PathBuf::from(format!("{SAVE_DIR}/world-{:08x}.json", seed.hash()))
+}
+
+/// What was found at a save path
+enum StoredSave {
+    Missing,
+    Loaded(WorldSave),
+    /// Couldn't be read, and couldn't be moved aside either
+    Unreadable,
+}
+
+/// A save that can't be read, like one from an older version, is moved aside first so a new world doesn't overwrite it
+fn read_world_save(path: &Path) -> StoredSave {
+    let Ok(mut file) = File::open(path) else { return StoredSave::Missing };
+
+    let mut string = String::new();
+    let _ = file.read_to_string(&mut string);
+
+    let err = match serde_json::from_str::<WorldSave>(&string) {
+        Ok(save) => return StoredSave::Loaded(save),
+        Err(err) => err,
+    };
+
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let backup = path.with_extension(format!("{secs}.bak"));
+
+    match fs::rename(path, &backup) {
+        Ok(_) => {
+            error!("failed to read {}: {err}, moved it to {}", path.display(), backup.display());
+            StoredSave::Missing
+        },
+        Err(rename_err) => {
+            error!("failed to read {}: {err}, and failed to move it aside: {rename_err}. The world won't be saved", path.display());
+            StoredSave::Unreadable
+        }
+    }
+}
+
+/// Picks the seed from the command line, then the save, then config.json. Without any of them the new world dialog
+/// asks for one, see `ui::new_world`. A `--seed` that doesn't match `SAVE_PATH` gets a save file of its own
+fn load_world_save(
+    mut commands: Commands,
+) {
+    let seed = WorldSeed::from_args();
+    let mut path = PathBuf::from(SAVE_PATH);
+    let mut stored = read_world_save(&path);
+
+    if let (Some(seed), StoredSave::Loaded(save)) = (&seed, &stored) {
+        if save.seed != seed.0 {
+            path = seed_save_path(seed);
+            info!("{SAVE_PATH} was made with a different seed, using {}", path.display());
+            stored = read_world_save(&path);
+        }
+    }
+
+    let writable = !matches!(stored, StoredSave::Unreadable);
+    let save = match stored {
+        StoredSave::Loaded(save) => Some(save),
+        _ => None,
+    };
+
+    let seed = seed
+        .or_else(|| save.as_ref().map(|save| WorldSeed(save.seed.clone())))
+        .or_else(WorldSeed::from_config);
+
+    let save = match seed {
+        Some(seed) => {
+            info!("world seed: {}", seed.0);
+            let save = save.unwrap_or_else(|| WorldSave::new(&seed));
+            commands.insert_resource(seed);
+            save
+        },
+        // the seed is filled in by the dialog
+        None => WorldSave::new(&WorldSeed(String::new())),
+    };
+
+    commands.insert_resource(save);
+    commands.insert_resource(SaveFile { path, writable });
+}
+
+/// Current id of every name in a save palette, `None` for names that don't exist anymore
+fn palette_ids(palette: &[String], kind: &str, current_id: impl Fn(&str) -> Option<u32>) -> Vec<Option<u32>> {
+    palette.iter().map(|name| {
+        let id = current_id(name);
+        if id.is_none() {
+            warn!("the save has {kind} \"{name}\", which doesn't exist anymore");
+        }
+        id
+    }).collect()
+}
+
+/// Moves the save over to the ids the registries assigned this time, so everything after loading can use them
+fn remap_palettes(
+    mut save: ResMut<WorldSave>,
+    block_database: Res<BlockDatabase>,
+    item_database: Res<ItemDatabase>,
+) {
+    if !save.block_palette.is_empty() {
+        // missing blocks turn into air
+        let block_ids: Vec<u32> = palette_ids(&save.block_palette, "block", |name| block_database.get_by_name(name).map(|block| block.id))
+            .into_iter()
+            .map(|id| id.unwrap_or(0))
+            .collect();
+
+        for chunk in save.chunks.values_mut() {
+            chunk.remap(&block_ids);
+        }
+    }
+
+    if !save.item_palette.is_empty() {
+        let item_ids = palette_ids(&save.item_palette, "item", |name| item_database.get_by_name(name).map(|item| item.id));
+
+        if let Some(player) = save.player.as_mut() {
+            // missing items are dropped from the inventory
+            for slot in player.items.iter_mut() {
+                *slot = slot.and_then(|(id, amount)| Some((item_ids.get(id as usize).copied().flatten()?, amount)));
+            }
+        }
+    }
+
+    save.block_palette = block_database.names().to_vec();
+    save.item_palette = item_database.names().to_vec();
+}
+
+/// A reload can only add names after the existing ones, so the palettes just take the new ones on
+fn extend_palettes(
+    mut ev_reloaded: EventReader<RegistriesReloaded>,
+    mut save: ResMut<WorldSave>,
+    block_database: Res<BlockDatabase>,
+    item_database: Res<ItemDatabase>,
+) {
+    if ev_reloaded.read().count() == 0 { return; }
+
+    save.block_palette = block_database.names().to_vec();
+    save.item_palette = item_database.names().to_vec();
+}
+
+fn load_player(
+    save: Res<WorldSave>,
+    item_database: Res<ItemDatabase>,
+    player: Single<(&mut Transform, &mut Inventory), With<Player>>,
+) {
+    let Some(player_save) = &save.player else { return };
+    let (mut transform, mut inventory) = player.into_inner();
+
+    transform.translation = Vec3::from_array(player_save.translation);
+
+    for (slot, saved_slot) in inventory.items.iter_mut().zip(player_save.items.iter()) {
+        // items a removed pack added are dropped
+        *slot = match saved_slot.and_then(|(id, amount)| Some((item_database.get(id)?, amount))) {
+            Some((item, amount)) => ItemSlot { item: Some(item), amount, wear: 0 },
+            None => ItemSlot { item: None, amount: 0, wear: 0 },
+        };
+    }
+
+    for (slot, wear) in inventory.items.iter_mut().zip(player_save.wear.iter()) {
+        slot.wear = *wear;
+    }
+}
+
+#[derive(Event)]
+pub struct SaveWorld;
+
+fn request_save(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut ev_app_exit: EventReader<AppExit>,
+    mut ev_save: EventWriter<SaveWorld>,
+) {
+    if keyboard.just_pressed(KeyCode::F5) || ev_app_exit.read().next().is_some() {
+        ev_save.send(SaveWorld);
+    }
+}
+
+fn save_world(
+    mut ev_save: EventReader<SaveWorld>,
+    mut save: ResMut<WorldSave>,
+    save_file: Res<SaveFile>,
+    world: Res<World>,
+    clock: Res<WorldClock>,
+    player: Option<Single<(&Transform, &Inventory), With<Player>>>,
+) {
+    if ev_save.read().next().is_none() { return; }
+    if !save_file.writable {
+        error!("not saving, {} couldn't be read and would be overwritten", save_file.path.display());
+        return;
+    }
+
+    save.time_of_day = clock.time;
+
+    for chunk in world.modified_chunks() {
+        save.chunks.insert(chunk.position, ChunkSave::from_chunk(chunk));
+    }
+
+    if let Some(player) = player {
+        let (transform, inventory) = player.into_inner();
+
+        save.player = Some(PlayerSave {
+            translation: transform.translation.to_array(),
+            items: inventory.items.iter()
+                .map(|slot| slot.item.map(|item| (item.id, slot.amount)))
+                .collect(),
+            wear: inventory.items.iter().map(|slot| slot.wear).collect(),
+        });
+    }
+
+    let string = match serde_json::to_string(&*save) {
+        Ok(string) => string,
+        Err(err) => {
+            error!("failed to serialize world: {err}");
+            return;
+        }
+    };
+
+    if let Some(dir) = save_file.path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+
+    match fs::write(&save_file.path, string) {
+        Ok(_) => info!("world saved to {}", save_file.path.display()),
+        Err(err) => error!("failed to write {}: {err}", save_file.path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRT: Block = Block { id: 1, is_solid: true, ..Block::AIR };
+    const STONE: Block = Block { id: 2, is_solid: true, ..Block::AIR };
+    const WATER: Block = Block { id: 3, is_liquid: true, ..Block::AIR };
+
+    fn block_database() -> BlockDatabase {
+        let names = ["rustaria:air", "rustaria:dirt", "rustaria:stone", "rustaria:water"];
+        BlockDatabase::new(vec![Block::AIR, DIRT, STONE, WATER], names.map(String::from).to_vec(), vec![None; 4])
+    }
+
+    /// Stone under dirt, with a pool of water that isn't full and stone in the background
+    fn chunk() -> Chunk {
+        let mut chunk = Chunk::new((3, -2));
+        for x in 0..CHUNK_WIDTH {
+            for y in 0..10 {
+                chunk.data[x][y] = if y < 6 { STONE } else { DIRT };
+                chunk.background_data[x][y] = STONE;
+            }
+        }
+        for x in 4..9 {
+            chunk.data[x][10] = Block { level: x as u8, ..WATER };
+        }
+        chunk
+    }
+
+    fn ids(layer: &[[Block; CHUNK_HEIGHT]; CHUNK_WIDTH]) -> Vec<u32> {
+        layer.iter().flatten().map(|block| block.id).collect()
+    }
+
+    #[test]
+    fn chunk_comes_back_the_same_from_a_save() {
+        let chunk = chunk();
+        let mut save = WorldSave::new(&WorldSeed("rustaria".to_string()));
+        save.chunks.insert(chunk.position, ChunkSave::from_chunk(&chunk));
+
+        let string = serde_json::to_string(&save).unwrap();
+        let save: WorldSave = serde_json::from_str(&string).unwrap();
+        let restored = save.get_chunk(chunk.position).unwrap().restore(chunk.position, &block_database());
+
+        assert!(ids(&restored.data) == ids(&chunk.data));
+        assert!(ids(&restored.background_data) == ids(&chunk.background_data));
+        let levels = |chunk: &Chunk| chunk.data.iter().flatten().map(|block| block.level).collect::<Vec<_>>();
+        assert!(levels(&restored) == levels(&chunk));
+    }
+
+    #[test]
+    fn palette_keeps_blocks_when_ids_change() {
+        let chunk = chunk();
+        let mut chunk_save = ChunkSave::from_chunk(&chunk);
+
+        // saved while dirt and stone had swapped ids, and with a block that is gone now
+        let palette = ["rustaria:air", "rustaria:stone", "rustaria:dirt", "rustaria:water", "rustaria:mud"].map(String::from);
+        let block_database = block_database();
+        let block_ids: Vec<u32> = palette_ids(&palette, "block", |name| block_database.get_by_name(name).map(|block| block.id))
+            .into_iter()
+            .map(|id| id.unwrap_or(0))
+            .collect();
+        assert_eq!(block_ids, [0, STONE.id, DIRT.id, WATER.id, 0]);
+
+        chunk_save.remap(&block_ids);
+        let restored = chunk_save.restore(chunk.position, &block_database);
+
+        assert_eq!(restored.data[0][0].id, DIRT.id);
+        assert_eq!(restored.data[0][8].id, STONE.id);
+        assert_eq!(restored.data[4][10].id, WATER.id);
+    }
+}
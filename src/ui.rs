@@ -12,6 +12,9 @@ use inventory::InventoryPlugin;
 mod crafting;
 use crafting::CraftingPlugin;
 
+mod new_world;
+use new_world::NewWorldPlugin;
+
 pub struct UiPlugin;
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
@@ -19,7 +22,8 @@ impl Plugin for UiPlugin {
             HotbarPlugin,
             ModeManagerPlugin,
             InventoryPlugin,
-            CraftingPlugin
+            CraftingPlugin,
+            NewWorldPlugin
         ));
     }
 }
\ No newline at end of file
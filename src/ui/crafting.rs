@@ -13,10 +13,17 @@ impl Plugin for CraftingPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_systems(OnEnter(UiState::Management), spawn_crafting_menu) 
-            .add_systems(Update, craft_items);
+            .add_systems(Update, (
+                // recipes can be reloaded while the menu is open
+                spawn_crafting_menu.run_if(in_state(UiState::Management).and(resource_changed::<CraftingRecipeDatabase>)),
+                craft_items,
+            ));
     }
 }
 
+#[derive(Component)]
+struct CraftingMenu;
+
 #[derive(Component)]
 struct CraftingSlot(u32);
 
@@ -25,7 +32,12 @@ fn spawn_crafting_menu(
     asset_server: Res<AssetServer>,
     recipe_database: Res<CraftingRecipeDatabase>,
     item_database: Res<ItemDatabase>,
+    q_menu: Query<Entity, With<CraftingMenu>>,
 ) {
+    for menu in q_menu.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+
     let canvas = commands.spawn(Node {
         display: Display::Grid,
         grid_auto_flow: GridAutoFlow::Row,
@@ -72,7 +84,7 @@ fn spawn_crafting_menu(
 
     commands.entity(canvas)
     .add_children(&slots)
-    .insert(StateScoped(UiState::Management));
+    .insert((CraftingMenu, StateScoped(UiState::Management)));
 }
 
 fn craft_items(
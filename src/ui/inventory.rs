@@ -4,7 +4,8 @@ use crate::{
     inventory::{
         item::{Item, ItemDatabase}, Inventory
     },
-    player::Player
+    player::Player,
+    registry::RegistriesReloaded
 };
 
 use super::mode_manager::UiState;
@@ -20,7 +21,8 @@ impl Plugin for InventoryPlugin {
             .add_systems(Update, (
                 update_inventory_of::<Player>,
                 move_items_of::<Player>,
-                stick_to_mouse
+                stick_to_mouse,
+                refresh_drag_item,
             ).run_if(in_state(UiState::Management)))
             .add_systems(OnExit(UiState::Management), return_taken_item::<Player>);
     }
@@ -36,6 +38,7 @@ struct StickToMouse;
 pub struct CurrentDragItem {
     pub item: Option<Item>,
     pub amount: u32,
+    pub wear: u32,
     pub slot_id: usize,
 }
 
@@ -43,6 +46,7 @@ impl CurrentDragItem {
     fn clear(&mut self) {
         self.item = None;
         self.amount = 0;
+        self.wear = 0;
         self.slot_id = 0;
     }
 }
@@ -183,6 +187,7 @@ fn move_items_of<S: Component>(
             if current_drag_item.item.is_none() {
                 current_drag_item.item = inventory.items[slot.0].item;
                 current_drag_item.amount = inventory.items[slot.0].amount;
+                current_drag_item.wear = inventory.items[slot.0].wear;
                 current_drag_item.slot_id = slot.0;
 
                 inventory.items[slot.0].clear();
@@ -191,6 +196,7 @@ fn move_items_of<S: Component>(
                 if inventory.items[slot.0].item.is_none() {
                     inventory.items[slot.0].item = current_drag_item.item;
                     inventory.items[slot.0].amount = current_drag_item.amount;
+                    inventory.items[slot.0].wear = current_drag_item.wear;
 
                     current_drag_item.clear();
                 }
@@ -198,6 +204,7 @@ fn move_items_of<S: Component>(
                     inventory.items[current_drag_item.slot_id] = inventory.items[slot.0];
                     inventory.items[slot.0].item = current_drag_item.item;
                     inventory.items[slot.0].amount = current_drag_item.amount;
+                    inventory.items[slot.0].wear = current_drag_item.wear;
                     current_drag_item.clear();
                 }
             }
@@ -214,10 +221,23 @@ fn return_taken_item<S: Component>(
     if current_drag_item.item.is_some() {
         inventory.items[current_drag_item.slot_id].item = current_drag_item.item;
         inventory.items[current_drag_item.slot_id].amount = current_drag_item.amount;
+        inventory.items[current_drag_item.slot_id].wear = current_drag_item.wear;
         current_drag_item.clear();
     }
 }
 
+fn refresh_drag_item(
+    mut ev_reloaded: EventReader<RegistriesReloaded>,
+    mut current_drag_item: ResMut<CurrentDragItem>,
+    item_database: Res<ItemDatabase>,
+) {
+    if ev_reloaded.read().count() == 0 { return; }
+
+    if let Some(item) = current_drag_item.item.as_mut() {
+        *item = item_database.get_by_id(item.id);
+    }
+}
+
 fn stick_to_mouse(
     curent_drag_item: Res<CurrentDragItem>,
     item_database: Res<ItemDatabase>,
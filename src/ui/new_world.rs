@@ -0,0 +1,110 @@
+use bevy::{
+    input::{keyboard::{Key, KeyboardInput}, ButtonState},
+    prelude::*
+};
+
+use crate::{save::WorldSave, world::seed::WorldSeed};
+
+/// Asks for the seed of a new world when neither the command line, a save nor config.json has one.
+/// The world doesn't stream in and time stands still until it's entered
+pub struct NewWorldPlugin;
+
+impl Plugin for NewWorldPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Startup, spawn_new_world_dialog.run_if(not(resource_exists::<WorldSeed>)))
+            .add_systems(Update, enter_seed.run_if(not(resource_exists::<WorldSeed>)));
+    }
+}
+
+#[derive(Component)]
+struct NewWorldDialog;
+
+#[derive(Component)]
+struct SeedField;
+
+fn spawn_new_world_dialog(
+    mut commands: Commands,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    // physics steps by virtual time, so the player doesn't fall while there is no ground yet
+    time.pause();
+
+    commands.spawn((
+        NewWorldDialog,
+        Node {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(12.),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.8)),
+    ))
+    .with_children(|dialog| {
+        dialog.spawn((
+            Text::new("New world"),
+            TextColor::WHITE,
+            TextFont {
+                font_size: 28.,
+                ..default()
+            },
+        ));
+        dialog.spawn((
+            Text::new(""),
+            TextColor::WHITE,
+            TextFont {
+                font_size: 20.,
+                ..default()
+            },
+            Node {
+                min_width: Val::Px(320.),
+                min_height: Val::Px(28.),
+                padding: UiRect::all(Val::Px(4.)),
+                ..default()
+            },
+            BackgroundColor(Color::WHITE.with_alpha(0.1)),
+            SeedField,
+        ));
+        dialog.spawn((
+            Text::new("Type a seed and press Enter. Leave it empty for a random one"),
+            TextColor(Color::WHITE.with_alpha(0.6)),
+            TextFont {
+                font_size: 14.,
+                ..default()
+            },
+        ));
+    });
+}
+
+fn enter_seed(
+    mut commands: Commands,
+    mut ev_keyboard: EventReader<KeyboardInput>,
+    mut time: ResMut<Time<Virtual>>,
+    mut save: ResMut<WorldSave>,
+    mut field: Single<&mut Text, With<SeedField>>,
+    dialog: Single<Entity, With<NewWorldDialog>>,
+) {
+    for ev in ev_keyboard.read() {
+        if ev.state != ButtonState::Pressed { continue; }
+
+        match &ev.logical_key {
+            Key::Character(text) => field.0.push_str(text),
+            Key::Space => field.0.push(' '),
+            Key::Backspace => { field.0.pop(); },
+            Key::Enter => {
+                let seed = if field.0.is_empty() { WorldSeed::random() } else { WorldSeed(field.0.clone()) };
+                info!("world seed: {}", seed.0);
+
+                save.seed = seed.0.clone();
+                commands.insert_resource(seed);
+                commands.entity(*dialog).despawn_recursive();
+                time.unpause();
+                return;
+            },
+            _ => {},
+        }
+    }
+}
@@ -1,83 +1,239 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::*;
 
+pub mod block_tick;
 pub mod chunk;
+pub mod clock;
+pub mod falling_block;
+pub mod generation;
+pub mod liquid;
+pub mod loot;
+pub mod seed;
 use block::{Block, BlockDatabase, BlockLayer};
+use block_structure::StructureBlock;
+use block_tick::BlockTickPlugin;
 use chunk::*;
+use chunk::light::{relight_around, update_block_light, UpdateBlockLight};
+use clock::ClockPlugin;
+use falling_block::FallingBlockPlugin;
+use generation::WorldGenPlugin;
+use liquid::LiquidPlugin;
+use loot::{LootPlugin, LootTables};
+use seed::WorldSeed;
 
 use crate::{
-    inventory::item::ItemDatabase,
+    inventory::item::{ItemDatabase, Tool},
     item_pickup::SpawnItemPickup,
+    player::Player,
+    registry::RegistriesReloaded,
+    save::{ChunkSave, WorldSave},
     BLOCK_SIZE_PX,
+    CHUNK_HEIGHT,
     CHUNK_WIDTH
 };
 
 pub struct WorldPlugin;
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(ChunkPlugin);
+        app.add_plugins((ChunkPlugin, WorldGenPlugin, LiquidPlugin, FallingBlockPlugin, BlockTickPlugin, LootPlugin, ClockPlugin));
 
         app.init_resource::<World>();
+        app.init_resource::<ChunkStreaming>();
         app.add_event::<SetBlock>();
 
         app
-            .add_systems(Startup, generate_world)
-            .add_systems(Update, set_block_at_position);
+            .add_systems(Update, (
+                // a new world waits for its seed
+                stream_chunks.before(generate_chunk_data).run_if(resource_exists::<WorldSeed>),
+                set_block_at_position.before(update_block_light),
+                refresh_chunk_blocks.before(set_block_at_position),
+            ));
     }
 }
 
+/// Structure parts that landed in one chunk, by the chunk that generated them
+type PendingStructures = HashMap<(i32, i32), Vec<StructureBlock>>;
+
 #[derive(Resource, Default)]
 pub struct World {
-    chunks: HashMap<i32, Chunk>,
-    chunk_entites: HashMap<i32, Entity>,
+    chunks: HashMap<(i32, i32), Chunk>,
+    chunk_entites: HashMap<(i32, i32), Entity>,
+    modified_chunks: HashSet<(i32, i32)>,
+    /// Structure parts that spilled over into a chunk, by the loaded chunk they came from. A chunk that unloads takes
+    /// its parts along and spills them again once it's generated or restored
+    pending_structures: HashMap<(i32, i32), PendingStructures>,
 }
 
 impl World {
     #[allow(unused)]
-    pub fn get_chunk(&self, x: i32) -> Option<&Chunk> {
-        if self.chunks.contains_key(&x) {
-            return Some(self.chunks.get(&x).unwrap());
+    pub fn get_chunk(&self, position: (i32, i32)) -> Option<&Chunk> {
+        if self.chunks.contains_key(&position) {
+            return Some(self.chunks.get(&position).unwrap());
         }
 
         None
     }
 
-    pub fn get_chunk_mut(&mut self, x: i32) -> Option<&mut Chunk> {
-        if self.chunks.contains_key(&x) {
-            return Some(self.chunks.get_mut(&x).unwrap());
+    pub fn get_chunk_mut(&mut self, position: (i32, i32)) -> Option<&mut Chunk> {
+        if self.chunks.contains_key(&position) {
+            return Some(self.chunks.get_mut(&position).unwrap());
         }
 
         None
     }
 
-    pub fn get_block(&self, x: f32, y: f32, layer: BlockLayer) -> Option<Block> {
-        let chunk_x = (x / CHUNK_WIDTH as f32 / BLOCK_SIZE_PX).floor() as i32;
+    /// Chunks that were changed after generation or restored from a save
+    pub fn modified_chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.modified_chunks.iter().filter_map(|x| self.chunks.get(x))
+    }
+
+    /// Positions of every chunk that is loaded right now
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn is_modified(&self, position: (i32, i32)) -> bool {
+        self.modified_chunks.contains(&position)
+    }
+
+    /// Chunk that contains a point in pixels, and the position of the block under it inside that chunk
+    pub fn block_position(position: Vec2) -> ((i32, i32), (usize, usize)) {
+        let block = (position / BLOCK_SIZE_PX).floor();
+        Self::locate_block((block.x as i32, block.y as i32))
+    }
 
-        let Some(chunk) = self.get_chunk(chunk_x) else { return None };
+    /// Chunk that contains a block given in world blocks, and the position of the block inside that chunk
+    pub fn locate_block((x, y): (i32, i32)) -> ((i32, i32), (usize, usize)) {
+        let (width, height) = (CHUNK_WIDTH as i32, CHUNK_HEIGHT as i32);
 
-        let (block_x, block_y) = ((x / BLOCK_SIZE_PX - (chunk_x as f32 * CHUNK_WIDTH as f32)) as usize,
-                                                (y / BLOCK_SIZE_PX) as usize);
+        (
+            (x.div_euclid(width), y.div_euclid(height)),
+            (x.rem_euclid(width) as usize, y.rem_euclid(height) as usize)
+        )
+    }
+
+    /// Foreground block at a position in world blocks, if its chunk is loaded
+    pub fn get_block_at(&self, position: (i32, i32)) -> Option<Block> {
+        let (chunk_position, (x, y)) = Self::locate_block(position);
+        self.get_chunk(chunk_position).map(|chunk| chunk.data[x][y])
+    }
+
+    /// Replaces a foreground block and marks its chunk as modified. Returns the chunk, if it's loaded
+    pub fn set_block_at(&mut self, position: (i32, i32), block: Block) -> Option<(i32, i32)> {
+        let chunk_position = self.replace_block_at(position, block)?;
+        self.modified_chunks.insert(chunk_position);
+        Some(chunk_position)
+    }
+
+    /// Replaces a foreground block without marking its chunk as modified, for changes that happen the same way
+    /// every time the chunk is generated. Returns the chunk, if it's loaded
+    pub fn replace_block_at(&mut self, position: (i32, i32), block: Block) -> Option<(i32, i32)> {
+        let (chunk_position, (x, y)) = Self::locate_block(position);
+        let chunk = self.get_chunk_mut(chunk_position)?;
+
+        chunk.data[x][y] = block.lit_like(chunk.data[x][y]);
+        Some(chunk_position)
+    }
+
+    /// Puts the chunk of a block given in world blocks into the save
+    pub fn mark_modified(&mut self, position: (i32, i32)) {
+        self.modified_chunks.insert(Self::locate_block(position).0);
+    }
+
+    pub fn get_block(&self, x: f32, y: f32, layer: BlockLayer) -> Option<Block> {
+        let (chunk_position, (block_x, block_y)) = Self::block_position(Vec2::new(x, y));
+
+        let chunk = self.get_chunk(chunk_position)?;
 
         match layer {
-            BlockLayer::Foreground => {
-                return Some(chunk.data[block_x][block_y]);
-            },
-            BlockLayer::Background => {
-                return Some(chunk.background_data[block_x][block_y]);
-            }
+            BlockLayer::Foreground => Some(chunk.data[block_x][block_y]),
+            BlockLayer::Background => Some(chunk.background_data[block_x][block_y]),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct ChunkStreaming {
+    /// Chunks this far to the sides of the player's chunk are generated or loaded
+    pub load_radius: i32,
+    /// Same for chunks above and below the player's chunk
+    pub vertical_load_radius: i32,
+    /// Extra distance a chunk has to be away before it's unloaded, so it doesn't flicker at the edge
+    pub unload_margin: i32,
+}
+
+impl Default for ChunkStreaming {
+    fn default() -> Self {
+        Self {
+            load_radius: 4,
+            vertical_load_radius: 2,
+            unload_margin: 2,
         }
-        
     }
 }
 
-fn generate_world (
-    mut ev_generate_chunk_data: EventWriter<GenerateChunkData>
+fn stream_chunks(
+    mut commands: Commands,
+    mut world: ResMut<World>,
+    mut world_save: ResMut<WorldSave>,
+    mut ev_generate_chunk_data: EventWriter<GenerateChunkData>,
+    mut ev_draw_chunk: EventWriter<DrawChunk>,
+    streaming: Res<ChunkStreaming>,
+    player_transform: Single<&Transform, With<Player>>,
 ) {
-    for x in (-4..4).rev() {
-        ev_generate_chunk_data.send(GenerateChunkData {
-            position: (x, 0)
+    let ((player_x, player_y), _) = World::block_position(player_transform.translation.truncate());
+
+    let mut to_generate = vec![];
+    for y in player_y - streaming.vertical_load_radius..=player_y + streaming.vertical_load_radius {
+        for x in player_x - streaming.load_radius..=player_x + streaming.load_radius {
+            if !world.chunks.contains_key(&(x, y)) {
+                to_generate.push((x, y));
+            }
+        }
+    }
+    // nearest chunks first, so the ground under the player shows up before the edges
+    to_generate.sort_by_key(|(x, y)| (x - player_x).pow(2) + (y - player_y).pow(2));
+
+    for position in to_generate {
+        ev_generate_chunk_data.send(GenerateChunkData { position });
+    }
+
+    let to_unload: Vec<(i32, i32)> = world.chunks.keys()
+        .copied()
+        .filter(|(x, y)| {
+            (x - player_x).abs() > streaming.load_radius + streaming.unload_margin
+                || (y - player_y).abs() > streaming.vertical_load_radius + streaming.unload_margin
+        })
+        .collect();
+
+    for &position in to_unload.iter() {
+        // keep edits around so they are restored when the chunk streams back in
+        if world.modified_chunks.remove(&position) {
+            world_save.chunks.insert(position, ChunkSave::from_chunk(&world.chunks[&position]));
+        }
+
+        world.chunks.remove(&position);
+        world.pending_structures.retain(|_, pending| {
+            pending.remove(&position);
+            !pending.is_empty()
         });
+
+        if let Some(entity) = world.chunk_entites.remove(&position) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    // only once every chunk is gone, so no light is taken from one that is about to go too
+    let mut touched = HashSet::new();
+    for &position in to_unload.iter() {
+        relight_around(&mut world, position, &mut touched);
+    }
+
+    for position in touched {
+        if let Some(chunk) = world.get_chunk(position) {
+            ev_draw_chunk.send(DrawChunk { chunk: *chunk });
+        }
     }
 }
 
@@ -87,50 +243,81 @@ pub struct SetBlock{
     pub position: Vec2,
     pub layer: BlockLayer,
     pub can_overwrite: bool,
+    /// Tool the block was broken with, `None` for hands or when the player didn't break it
+    pub tool: Option<Tool>,
 }
 
 fn set_block_at_position(
     mut ev_break_block: EventReader<SetBlock>,
     mut world: ResMut<World>,
-    mut ev_update_light: EventWriter<UpdateChunkLight>,
+    mut ev_update_light: EventWriter<UpdateBlockLight>,
     mut ev_spawn_item_pickup: EventWriter<SpawnItemPickup>,
     item_database: Res<ItemDatabase>,
-    block_database: Res<BlockDatabase>,
+    loot_tables: Res<LootTables>,
 ) {
-    for ev in ev_break_block.read() {
-        let chunk_x= (ev.position.x / CHUNK_WIDTH as f32 / BLOCK_SIZE_PX).floor() as i32;
+    let mut rng = rand::thread_rng();
 
-        let Some(chunk) = world.get_chunk_mut(chunk_x) else { return; };
+    for ev in ev_break_block.read() {
+        let (chunk_position, (block_x, block_y)) = World::block_position(ev.position);
 
-        let (block_x, block_y) = ((ev.position.x / BLOCK_SIZE_PX - (chunk_x as f32 * CHUNK_WIDTH as f32)) as usize,
-                                                (ev.position.y / BLOCK_SIZE_PX) as usize);
+        let Some(chunk) = world.get_chunk_mut(chunk_position) else { continue; };
 
         let block_to_replace;
 
         match ev.layer {
             BlockLayer::Foreground => { 
                 if !ev.can_overwrite && chunk.data[block_x][block_y].id != 0 {
-                    return;
+                    continue;
                 }
                 block_to_replace = chunk.data[block_x][block_y];
-                chunk.data[block_x][block_y] = ev.block;
+                chunk.data[block_x][block_y] = ev.block.lit_like(block_to_replace);
             },
             BlockLayer::Background => { 
                 if !ev.can_overwrite && chunk.background_data[block_x][block_y].id != 0 {
-                    return;
+                    continue;
                 }
                 block_to_replace = chunk.background_data[block_x][block_y];
-                chunk.background_data[block_x][block_y] = ev.block;
+                chunk.background_data[block_x][block_y] = ev.block.lit_like(block_to_replace);
             }
         }
 
-        if ev.block.id == 0 && block_to_replace.id != 0 {
-            ev_spawn_item_pickup.send(SpawnItemPickup {
-                item: item_database.get_by_id(block_database.get_by_id(block_to_replace.id).drop_item),
-                position: Vec2::new(ev.position.x + BLOCK_SIZE_PX/2., ev.position.y + BLOCK_SIZE_PX/2.),
-            });
+        // liquids are taken with a bucket, not broken
+        if ev.block.id == 0 && block_to_replace.id != 0 && !block_to_replace.is_liquid && block_to_replace.drops_with(ev.tool) {
+            // silk touch is up to the conditions of the loot table
+            let drops = match loot_tables.get(block_to_replace.id) {
+                Some(table) => table.roll(ev.tool, &mut rng, &item_database),
+                None => block_to_replace.drop_item.map(|id| (item_database.get_by_id(id), 1)).into_iter().collect(),
+            };
+
+            for (item, amount) in drops {
+                ev_spawn_item_pickup.send(SpawnItemPickup {
+                    item,
+                    amount,
+                    position: Vec2::new(ev.position.x + BLOCK_SIZE_PX/2., ev.position.y + BLOCK_SIZE_PX/2.),
+                });
+            }
         }
 
-        ev_update_light.send(UpdateChunkLight { position: chunk_x });
+        world.modified_chunks.insert(chunk_position);
+        ev_update_light.send(UpdateBlockLight {
+            position: (chunk_position.0 * CHUNK_WIDTH as i32 + block_x as i32, chunk_position.1 * CHUNK_HEIGHT as i32 + block_y as i32),
+        });
+    }
+}
+
+/// Chunks hold copies of blocks, so after a reload they fetch them again. Chunks where that changed anything are relit,
+/// which redraws them
+fn refresh_chunk_blocks(
+    mut ev_reloaded: EventReader<RegistriesReloaded>,
+    mut ev_update_light: EventWriter<UpdateChunkLight>,
+    mut world: ResMut<World>,
+    block_database: Res<BlockDatabase>,
+) {
+    if ev_reloaded.read().count() == 0 { return; }
+
+    for (position, chunk) in world.chunks.iter_mut() {
+        if chunk.refresh_blocks(&block_database) {
+            ev_update_light.send(UpdateChunkLight { position: *position });
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,424 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{BLOCK_SIZE_PX, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+use super::{
+    chunk::{block::{Block, BlockDatabase, BlockLayer}, generate_chunk_data, GenerateChunkData},
+    clock::WorldClock,
+    generation::WorldGenPipeline,
+    set_block_at_position,
+    SetBlock,
+    World
+};
+
+mod grass;
+use grass::Grass;
+
+mod sapling;
+use sapling::Sapling;
+
+/// Seconds between two block ticks
+const TICK_SECONDS: f32 = 0.05;
+
+/// Blocks picked at random in every loaded chunk on each tick
+const RANDOM_TICKS_PER_CHUNK: usize = 3;
+
+pub struct BlockTickPlugin;
+
+impl Plugin for BlockTickPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockTicks>();
+        app.init_resource::<BlockBehaviours>();
+        app.add_systems(PostStartup, register_behaviours);
+        app.add_systems(Update, (
+            notify_added_blocks.after(generate_chunk_data).after(set_block_at_position),
+            tick_blocks.after(notify_added_blocks),
+        ));
+    }
+}
+
+/// What a block does on its own. Every method does nothing unless the behaviour overrides it
+pub trait BlockBehaviour: Send + Sync {
+    /// Called now and then for a block picked at random in a loaded chunk
+    fn random_tick(&self, _ctx: &mut TickContext, _position: (i32, i32), _block: Block) {}
+
+    /// Called once a tick asked for with `TickContext::schedule` is due
+    fn scheduled_tick(&self, _ctx: &mut TickContext, _position: (i32, i32), _block: Block) {}
+
+    /// Called when the block is placed and when its chunk is loaded, so it can schedule its first tick
+    fn added(&self, _ctx: &mut TickContext, _position: (i32, i32), _block: Block) {}
+}
+
+/// Block behaviours by block id
+#[derive(Resource, Default)]
+pub struct BlockBehaviours(HashMap<u32, Box<dyn BlockBehaviour>>);
+
+impl BlockBehaviours {
+    pub fn register(&mut self, block_database: &BlockDatabase, name: &str, behaviour: impl BlockBehaviour + 'static) {
+        match block_database.get_by_name(name) {
+            Some(block) => { self.0.insert(block.id, Box::new(behaviour)); },
+            None => error!("can't register a behaviour for unknown block \"{name}\""),
+        }
+    }
+}
+
+/// A scheduled tick only runs if the block that asked for it is still there
+type ScheduledTick = ((i32, i32), u32);
+
+#[derive(Resource)]
+pub struct BlockTicks {
+    timer: Timer,
+    tick: u64,
+    /// Ticks waiting to run, by the tick they are due on
+    scheduled: BTreeMap<u64, Vec<ScheduledTick>>,
+    /// Changes made by `added`, sent along with the next tick's
+    pending_changes: Vec<SetBlock>,
+    rng: StdRng,
+}
+
+impl Default for BlockTicks {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(TICK_SECONDS, TimerMode::Repeating),
+            tick: 0,
+            scheduled: BTreeMap::new(),
+            pending_changes: vec![],
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+/// Everything a behaviour can read or change while it ticks. Changes are sent as `SetBlock` events,
+/// so drops, lighting, liquids and falling blocks all react to them
+pub struct TickContext<'a> {
+    pub world: &'a World,
+    pub block_database: &'a BlockDatabase,
+    pub pipeline: &'a WorldGenPipeline,
+    pub clock: &'a WorldClock,
+    pub rng: &'a mut StdRng,
+    tick: u64,
+    scheduled: &'a mut BTreeMap<u64, Vec<ScheduledTick>>,
+    changes: Vec<SetBlock>,
+}
+
+impl TickContext<'_> {
+    /// Asks for a scheduled tick of the block at `position`, `delay` ticks from now
+    pub fn schedule(&mut self, position: (i32, i32), delay: u64) {
+        let Some(block) = self.world.get_block_at(position) else { return };
+        self.scheduled.entry(self.tick + delay.max(1)).or_default().push((position, block.id));
+    }
+
+    pub fn set_block(&mut self, position: (i32, i32), block: Block, layer: BlockLayer) {
+        self.changes.push(SetBlock {
+            block,
+            position: Vec2::new(position.0 as f32, position.1 as f32) * BLOCK_SIZE_PX,
+            layer,
+            can_overwrite: true,
+            tool: None,
+        });
+    }
+}
+
+fn register_behaviours(
+    mut behaviours: ResMut<BlockBehaviours>,
+    block_database: Res<BlockDatabase>,
+) {
+    behaviours.register(&block_database, "rustaria:grass", Grass::new(&block_database));
+
+    for (id, name) in block_database.names().iter().enumerate() {
+        if let Some(species) = block_database.tree_species(id as u32) {
+            behaviours.register(&block_database, name, Sapling { species: species.to_string() });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn notify_added_blocks(
+    mut ev_generate: EventReader<GenerateChunkData>,
+    mut ev_set_block: EventReader<SetBlock>,
+    mut ticks: ResMut<BlockTicks>,
+    behaviours: Res<BlockBehaviours>,
+    world: Res<World>,
+    block_database: Res<BlockDatabase>,
+    pipeline: Res<WorldGenPipeline>,
+    clock: Res<WorldClock>,
+) {
+    let mut added = vec![];
+
+    for ev in ev_generate.read() {
+        let Some(chunk) = world.get_chunk(ev.position) else { continue };
+        let (left, bottom) = (ev.position.0 * CHUNK_WIDTH as i32, ev.position.1 * CHUNK_HEIGHT as i32);
+
+        for x in 0..CHUNK_WIDTH {
+            for y in 0..CHUNK_HEIGHT {
+                if behaviours.0.contains_key(&chunk.data[x][y].id) {
+                    added.push((left + x as i32, bottom + y as i32));
+                }
+            }
+        }
+    }
+
+    for ev in ev_set_block.read() {
+        if let BlockLayer::Background = ev.layer { continue; }
+        if behaviours.0.contains_key(&ev.block.id) {
+            let block = (ev.position / BLOCK_SIZE_PX).floor();
+            added.push((block.x as i32, block.y as i32));
+        }
+    }
+
+    if added.is_empty() { return; }
+
+    let ticks = &mut *ticks;
+    let mut ctx = TickContext {
+        world: &world,
+        block_database: &block_database,
+        pipeline: &pipeline,
+        clock: &clock,
+        rng: &mut ticks.rng,
+        tick: ticks.tick,
+        scheduled: &mut ticks.scheduled,
+        changes: vec![],
+    };
+
+    for position in added {
+        let Some(block) = world.get_block_at(position) else { continue };
+        if let Some(behaviour) = behaviours.0.get(&block.id) {
+            behaviour.added(&mut ctx, position, block);
+        }
+    }
+
+    let changes = ctx.changes;
+    ticks.pending_changes.extend(changes);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn tick_blocks(
+    time: Res<Time>,
+    mut ev_changes: EventWriter<SetBlock>,
+    mut ticks: ResMut<BlockTicks>,
+    behaviours: Res<BlockBehaviours>,
+    world: Res<World>,
+    block_database: Res<BlockDatabase>,
+    pipeline: Res<WorldGenPipeline>,
+    clock: Res<WorldClock>,
+) {
+    if !ticks.timer.tick(time.delta()).just_finished() { return; }
+    ticks.tick += 1;
+
+    let ticks = &mut *ticks;
+    ev_changes.send_batch(ticks.pending_changes.drain(..));
+
+    // everything due up to and including this tick
+    let later = ticks.scheduled.split_off(&(ticks.tick + 1));
+    let due = std::mem::replace(&mut ticks.scheduled, later);
+
+    let mut ctx = TickContext {
+        world: &world,
+        block_database: &block_database,
+        pipeline: &pipeline,
+        clock: &clock,
+        rng: &mut ticks.rng,
+        tick: ticks.tick,
+        scheduled: &mut ticks.scheduled,
+        changes: vec![],
+    };
+
+    for (position, id) in due.into_values().flatten() {
+        // the block was replaced or its chunk unloaded since. Loading it again calls `added`, which reschedules
+        let Some(block) = world.get_block_at(position).filter(|block| block.id == id) else { continue };
+        if let Some(behaviour) = behaviours.0.get(&id) {
+            behaviour.scheduled_tick(&mut ctx, position, block);
+        }
+    }
+
+    let chunks: Vec<(i32, i32)> = world.loaded_chunks().collect();
+    for (chunk_x, chunk_y) in chunks {
+        for _ in 0..RANDOM_TICKS_PER_CHUNK {
+            let x = chunk_x * CHUNK_WIDTH as i32 + ctx.rng.gen_range(0..CHUNK_WIDTH as i32);
+            let y = chunk_y * CHUNK_HEIGHT as i32 + ctx.rng.gen_range(0..CHUNK_HEIGHT as i32);
+
+            let Some(block) = world.get_block_at((x, y)) else { continue };
+            if let Some(behaviour) = behaviours.0.get(&block.id) {
+                behaviour.random_tick(&mut ctx, (x, y), block);
+            }
+        }
+    }
+
+    ev_changes.send_batch(ctx.changes);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{Arc, Mutex}, time::Duration};
+
+    use super::*;
+    use crate::{pack::ContentPacks, world::{chunk::{block::MAX_LIGHT_LEVEL, Chunk}, generation::load_pipeline}};
+
+    const TICKER: Block = Block { id: 1, ..Block::AIR };
+
+    /// Ticks seen by `Recorder`, as `(kind, position, tick)`
+    type Log = Arc<Mutex<Vec<(&'static str, (i32, i32), u64)>>>;
+
+    /// Writes down every tick it gets and schedules one `SCHEDULE_DELAY` ticks after it's added
+    struct Recorder(Log);
+
+    const SCHEDULE_DELAY: u64 = 3;
+
+    impl BlockBehaviour for Recorder {
+        fn random_tick(&self, ctx: &mut TickContext, position: (i32, i32), _block: Block) {
+            self.0.lock().unwrap().push(("random", position, ctx.tick));
+        }
+
+        fn scheduled_tick(&self, ctx: &mut TickContext, position: (i32, i32), _block: Block) {
+            self.0.lock().unwrap().push(("scheduled", position, ctx.tick));
+        }
+
+        fn added(&self, ctx: &mut TickContext, position: (i32, i32), _block: Block) {
+            ctx.schedule(position, SCHEDULE_DELAY);
+        }
+    }
+
+    fn pipeline() -> WorldGenPipeline {
+        load_pipeline(&ContentPacks::base()).expect("the base assets should have a world generation pipeline")
+    }
+
+    /// An app running the tick systems over `world`, with `TICKER` recording into the returned log
+    fn tick_app(world: World) -> (App, Log) {
+        let log = Log::default();
+        let mut behaviours = BlockBehaviours::default();
+        behaviours.0.insert(TICKER.id, Box::new(Recorder(log.clone())));
+        let names = ["rustaria:air", "test:ticker"].map(String::from).to_vec();
+
+        let mut app = App::new();
+        app
+            .add_event::<SetBlock>()
+            .add_event::<GenerateChunkData>()
+            .init_resource::<Time>()
+            .init_resource::<WorldClock>()
+            .insert_resource(world)
+            .insert_resource(BlockDatabase::new(vec![Block::AIR, TICKER], names, vec![None; 2]))
+            .insert_resource(pipeline())
+            .insert_resource(BlockTicks { rng: StdRng::seed_from_u64(1), ..default() })
+            .insert_resource(behaviours)
+            .add_systems(Update, (notify_added_blocks, tick_blocks.after(notify_added_blocks)));
+
+        (app, log)
+    }
+
+    fn tick(app: &mut App) {
+        app.world_mut().resource_mut::<Time>().advance_by(Duration::from_secs_f32(TICK_SECONDS));
+        app.update();
+    }
+
+    fn place(app: &mut App, position: (i32, i32), block: Block) {
+        app.world_mut().resource_mut::<World>().replace_block_at(position, block);
+        app.world_mut().send_event(SetBlock {
+            block,
+            position: Vec2::new(position.0 as f32, position.1 as f32) * BLOCK_SIZE_PX,
+            layer: BlockLayer::Foreground,
+            can_overwrite: true,
+            tool: None,
+        });
+    }
+
+    fn empty_world(chunks: &[(i32, i32)]) -> World {
+        let mut world = World::default();
+        for &position in chunks {
+            world.chunks.insert(position, Chunk::new(position));
+        }
+        world
+    }
+
+    #[test]
+    fn scheduled_tick_runs_once_it_is_due() {
+        let (mut app, log) = tick_app(empty_world(&[(0, 0)]));
+        place(&mut app, (5, 5), TICKER);
+
+        for _ in 0..SCHEDULE_DELAY + 2 {
+            tick(&mut app);
+        }
+
+        let scheduled: Vec<_> = log.lock().unwrap().iter().filter(|entry| entry.0 == "scheduled").copied().collect();
+        assert_eq!(scheduled, [("scheduled", (5, 5), SCHEDULE_DELAY)]);
+    }
+
+    #[test]
+    fn scheduled_tick_is_dropped_once_the_block_is_replaced() {
+        let (mut app, log) = tick_app(empty_world(&[(0, 0)]));
+        place(&mut app, (5, 5), TICKER);
+        tick(&mut app);
+        app.world_mut().resource_mut::<World>().replace_block_at((5, 5), Block::AIR);
+
+        for _ in 0..SCHEDULE_DELAY + 2 {
+            tick(&mut app);
+        }
+
+        assert!(log.lock().unwrap().iter().all(|entry| entry.0 != "scheduled"));
+    }
+
+    #[test]
+    fn every_loaded_chunk_gets_its_random_ticks() {
+        let chunks = [(0, 0), (1, 0)];
+        let mut world = empty_world(&chunks);
+        for x in 0..2 * CHUNK_WIDTH as i32 {
+            for y in 0..CHUNK_HEIGHT as i32 {
+                world.replace_block_at((x, y), TICKER);
+            }
+        }
+        let (mut app, log) = tick_app(world);
+
+        tick(&mut app);
+
+        let log = log.lock().unwrap();
+        for (chunk_x, _) in chunks {
+            let in_chunk = log.iter()
+                .filter(|(kind, (x, _), _)| *kind == "random" && x.div_euclid(CHUNK_WIDTH as i32) == chunk_x)
+                .count();
+            assert_eq!(in_chunk, RANDOM_TICKS_PER_CHUNK);
+        }
+    }
+
+    #[test]
+    fn grass_only_spreads_by_daylight() {
+        const DIRT: Block = Block { id: 1, is_solid: true, ..Block::AIR };
+        const GRASS: Block = Block { id: 2, is_solid: true, ..Block::AIR };
+
+        let names = ["rustaria:air", "rustaria:dirt", "rustaria:grass"].map(String::from).to_vec();
+        let block_database = BlockDatabase::new(vec![Block::AIR, DIRT, GRASS], names, vec![None; 3]);
+        let grass = Grass::new(&block_database);
+        let pipeline = pipeline();
+
+        // a dirt floor open to the sky with a single grass block
+        let mut world = empty_world(&[(0, 0)]);
+        for x in 0..CHUNK_WIDTH as i32 {
+            world.replace_block_at((x, 0), DIRT);
+            world.chunks.get_mut(&(0, 0)).unwrap().data[x as usize][1].sky_light = MAX_LIGHT_LEVEL;
+        }
+        world.replace_block_at((5, 0), GRASS);
+
+        let spread_at = |time: f32| {
+            let clock = WorldClock { time };
+            let (mut rng, mut scheduled) = (StdRng::seed_from_u64(1), BTreeMap::new());
+            let mut ctx = TickContext {
+                world: &world,
+                block_database: &block_database,
+                pipeline: &pipeline,
+                clock: &clock,
+                rng: &mut rng,
+                tick: 0,
+                scheduled: &mut scheduled,
+                changes: vec![],
+            };
+            for _ in 0..200 {
+                grass.random_tick(&mut ctx, (5, 0), GRASS);
+            }
+            ctx.changes.iter().any(|change| change.block.id == GRASS.id)
+        };
+
+        assert!(spread_at(0.5));
+        assert!(!spread_at(0.));
+    }
+}
@@ -0,0 +1,47 @@
+use rand::Rng;
+
+use crate::world::chunk::block::{Block, BlockDatabase, BlockLayer};
+
+use super::{BlockBehaviour, TickContext};
+
+/// Dirt needs at least this much light above it for grass to spread onto it
+const MIN_SPREAD_LIGHT: u8 = 9;
+
+/// Spreads onto lit dirt nearby, and dies back to dirt once something solid covers it
+pub struct Grass {
+    grass: Block,
+    dirt: Block,
+}
+
+impl Grass {
+    pub fn new(block_database: &BlockDatabase) -> Self {
+        let block = |name: &str| block_database.get_by_name(name).unwrap_or(Block::AIR);
+        Self { grass: block("rustaria:grass"), dirt: block("rustaria:dirt") }
+    }
+
+    /// Whether grass could live under `above`
+    fn is_uncovered(above: Block) -> bool {
+        !above.is_solid && !above.is_liquid
+    }
+}
+
+impl BlockBehaviour for Grass {
+    fn random_tick(&self, ctx: &mut TickContext, (x, y): (i32, i32), _block: Block) {
+        let Some(above) = ctx.world.get_block_at((x, y+1)) else { return };
+        if !Self::is_uncovered(above) {
+            ctx.set_block((x, y), self.dirt, BlockLayer::Foreground);
+            return;
+        }
+
+        // mostly sideways and down, so it creeps over hills and down slopes
+        let target = (x + ctx.rng.gen_range(-1..=1), y + ctx.rng.gen_range(-3..=1));
+
+        let Some(block) = ctx.world.get_block_at(target) else { return };
+        if block.id != self.dirt.id { return; }
+
+        let Some(above) = ctx.world.get_block_at((target.0, target.1 + 1)) else { return };
+        if Self::is_uncovered(above) && ctx.clock.light_level(&above) >= MIN_SPREAD_LIGHT {
+            ctx.set_block(target, self.grass, BlockLayer::Foreground);
+        }
+    }
+}
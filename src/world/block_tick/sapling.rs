@@ -0,0 +1,69 @@
+use rand::Rng;
+
+use crate::world::chunk::block::{Block, BlockLayer};
+
+use super::{BlockBehaviour, TickContext};
+
+/// Ticks between a sapling being placed and its first try at growing
+const MIN_GROW_DELAY: u64 = 600;
+const MAX_GROW_DELAY: u64 = 1200;
+
+/// Grows into a tree of `species` some time after it's planted, once there is room for it
+pub struct Sapling {
+    pub species: String,
+}
+
+impl BlockBehaviour for Sapling {
+    fn added(&self, ctx: &mut TickContext, position: (i32, i32), _block: Block) {
+        let delay = ctx.rng.gen_range(MIN_GROW_DELAY..=MAX_GROW_DELAY);
+        ctx.schedule(position, delay);
+    }
+
+    fn scheduled_tick(&self, ctx: &mut TickContext, (x, y): (i32, i32), block: Block) {
+        if !ctx.world.get_block_at((x, y-1)).is_some_and(|ground| ground.is_solid) {
+            return;
+        }
+
+        let Some(species) = ctx.pipeline.tree_species(&self.species) else { return };
+        let Some(tree) = species.grow(ctx.rng, ctx.block_database) else { return };
+
+        let cell = |i: usize, j: usize| (x + i as i32 - tree.anchor.0 as i32, y + j as i32 - tree.anchor.1 as i32);
+
+        // the trunk needs free cells all the way up, leaves just skip whatever is in their way
+        // and roots grow into the ground under the sapling
+        let mut blocked = false;
+        for j in 0..tree.height() {
+            for i in 0..tree.width() {
+                if tree.data[j][i] == 0 && tree.bg_data[j][i] == 0 { continue; }
+                let is_root = cell(i, j).1 < y;
+
+                match ctx.world.get_block_at(cell(i, j)) {
+                    Some(existing) => blocked |= !is_root && tree.bg_data[j][i] != 0 && existing.id != 0 && cell(i, j) != (x, y),
+                    // part of the tree would be in a chunk that isn't loaded
+                    None => blocked = true,
+                }
+            }
+        }
+
+        if blocked {
+            self.added(ctx, (x, y), block);
+            return;
+        }
+
+        for j in 0..tree.height() {
+            for i in 0..tree.width() {
+                let position = cell(i, j);
+                let Some(existing) = ctx.world.get_block_at(position) else { continue };
+
+                if tree.data[j][i] != 0 && (existing.id == 0 || position == (x, y)) {
+                    let block = ctx.block_database.get_by_id(tree.data[j][i]);
+                    ctx.set_block(position, block, BlockLayer::Foreground);
+                }
+                if tree.bg_data[j][i] != 0 {
+                    let block = ctx.block_database.get_by_id(tree.bg_data[j][i]);
+                    ctx.set_block(position, block, BlockLayer::Background);
+                }
+            }
+        }
+    }
+}
@@ -1,83 +1,121 @@
-use std::{collections::VecDeque, hash::{Hash, Hasher}};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use bevy_rapier2d::prelude::*;
 use bevy::{asset::RenderAssetUsages, prelude::*, render::mesh::{Indices, PrimitiveTopology}};
-use noise::{NoiseFn, Perlin, Simplex};
-use rand::Rng;
 
 pub mod block;
 use block::*;
 
-mod block_structure;
-use block_structure::*;
+pub mod atlas;
+use atlas::BlockAtlas;
 
-use crate::{CHUNK_WIDTH, CHUNK_HEIGHT, SEED};
+pub mod block_structure;
+use block_structure::StructureBlock;
+
+pub mod light;
+use light::{relight_chunk, update_block_light, UpdateBlockLight};
+
+pub mod material;
+use material::{ChunkMaterialHandle, ChunkMaterialPlugin, ATTRIBUTE_SKY_LIGHT};
+
+use crate::{save::WorldSave, CHUNK_WIDTH, CHUNK_HEIGHT};
+
+use super::{generation::WorldGenPipeline, seed::WorldSeed};
 use crate::BLOCK_SIZE_PX;
+
 pub struct ChunkPlugin;
 
 impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(BlockPlugin);
+        app.add_plugins(ChunkMaterialPlugin);
         app.insert_resource(LightUpdateQueue(VecDeque::new()));
         app
             .add_event::<GenerateChunkData>()
             .add_event::<DrawChunk>()
-            .add_event::<UpdateChunkLight>();
+            .add_event::<UpdateChunkLight>()
+            .add_event::<UpdateBlockLight>();
         app.add_systems(Update, (
-            generate_chunk_data,
+            generate_chunk_data.run_if(resource_exists::<WorldSeed>),
             push_light_updates.after(generate_chunk_data),
             update_light.after(push_light_updates),
-            draw_chunk.after(update_light),
+            update_block_light.after(update_light),
+            draw_chunk.after(update_block_light),
         ));
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Chunk {
-    pub position: i32,
+    pub position: (i32, i32),
     pub data: [[Block; CHUNK_HEIGHT]; CHUNK_WIDTH],
     pub background_data: [[Block; CHUNK_HEIGHT]; CHUNK_WIDTH],
+    /// Whether fresh terrain leaves each column open to the sky right above the chunk. Light takes that as sun light
+    /// coming in while the chunk above isn't loaded
+    pub open_sky: [bool; CHUNK_WIDTH],
 }
 
 impl Chunk {
-    pub const PLACEHOLDER: Chunk = Self {
-        position: i32::MAX,
-        data: [[Block::AIR; CHUNK_HEIGHT]; CHUNK_WIDTH],
-        background_data: [[Block::AIR; CHUNK_HEIGHT]; CHUNK_WIDTH]
-    };
+    /// Fetches every block from the database again, keeping the light and liquid level of its cell.
+    /// Returns whether any block changed
+    pub fn refresh_blocks(&mut self, block_database: &BlockDatabase) -> bool {
+        let mut changed = false;
+
+        for column in self.data.iter_mut().chain(self.background_data.iter_mut()) {
+            for block in column.iter_mut() {
+                let fresh = Block {
+                    sky_light: block.sky_light,
+                    block_light: block.block_light,
+                    level: block.level,
+                    ..block_database.get_by_id(block.id)
+                };
+                changed |= fresh != *block;
+                *block = fresh;
+            }
+        }
+
+        changed
+    }
 
-    pub fn new(_x: i32) -> Self {
+    pub fn new(position: (i32, i32)) -> Self {
         Self {
-            position: _x,
+            position,
             data: [[Block::AIR; CHUNK_HEIGHT]; CHUNK_WIDTH],
-            background_data: [[Block::AIR; CHUNK_HEIGHT]; CHUNK_WIDTH]
+            background_data: [[Block::AIR; CHUNK_HEIGHT]; CHUNK_WIDTH],
+            open_sky: [false; CHUNK_WIDTH],
         }
     }
 
-    pub fn create_mesh(&self) -> (Mesh, Mesh, Mesh, Collider) {
+    pub fn create_mesh(&self, atlas: &BlockAtlas) -> (Mesh, Mesh, Mesh, Mesh, Collider) {
 
         let mut vertices: Vec<[f32; 3]> = vec![];
         let mut not_solid_vertices: Vec<[f32; 3]> = vec![];
         let mut bg_vertices: Vec<[f32; 3]> = vec![];
+        let mut liquid_vertices: Vec<[f32; 3]> = vec![];
         let mut collider_vertices: Vec<Vec2> = vec![];
 
         let mut indices: Vec<u32> = vec![];
         let mut not_solid_indices: Vec<u32> = vec![];
         let mut bg_indices: Vec<u32> = vec![];
+        let mut liquid_indices: Vec<u32> = vec![];
         let mut collider_indices: Vec<[u32; 3]> = vec![];
         
         let mut colors: Vec<[f32; 4]> = vec![];
         let mut not_solid_colors: Vec<[f32; 4]> = vec![];
         let mut bg_colors: Vec<[f32; 4]> = vec![];
+        let mut liquid_colors: Vec<[f32; 4]> = vec![];
+
+        let mut sky: Vec<f32> = vec![];
+        let mut not_solid_sky: Vec<f32> = vec![];
+        let mut bg_sky: Vec<f32> = vec![];
+        let mut liquid_sky: Vec<f32> = vec![];
 
         let mut uvs: Vec<[f32; 2]> = vec![];
         let mut not_solid_uvs: Vec<[f32; 2]> = vec![];
         let mut bg_uvs: Vec<[f32; 2]> = vec![];
+        let mut liquid_uvs: Vec<[f32; 2]> = vec![];
 
         for y in 0..CHUNK_HEIGHT {
             for x in 0..CHUNK_WIDTH {
-                let uv_block_size = BLOCK_SIZE_PX/256.;
-
                 if self.data[x][y].is_solid {
                     vertices.extend([
                         [x as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, 0.0],
@@ -93,18 +131,17 @@ impl Chunk {
                         Vec2::new(x as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX),
                     ]);
 
-                    let light = self.data[x][y].light as f32 / 15.;
-                    let color = [light, light, light, 1.0];
+                    let color = self.data[x][y].block_light.to_color(1.0);
                     colors.extend([color; 4]);
+                    sky.extend([self.data[x][y].sky_brightness(1.0); 4]);
 
-                    let uv_offset_x = (self.data[x][y].id as f32 * BLOCK_SIZE_PX) / 256.;
-                    let uv_offset_y = ((self.data[x][y].id / 16) as f32 * 16. * BLOCK_SIZE_PX) / 256.;
+                    let uv = atlas.tile_uv(self.data[x][y].tile);
 
                     uvs.extend([
-                        [uv_offset_x, uv_offset_y],
-                        [uv_offset_x + uv_block_size, uv_offset_y],
-                        [uv_offset_x + uv_block_size, uv_offset_y + uv_block_size],
-                        [uv_offset_x, uv_offset_y + uv_block_size],
+                        [uv.min.x, uv.min.y],
+                        [uv.max.x, uv.min.y],
+                        [uv.max.x, uv.max.y],
+                        [uv.min.x, uv.max.y],
                     ]);
 
                     let base_index = vertices.len() as u32 - 4;
@@ -118,6 +155,38 @@ impl Chunk {
                         [base_index, base_index + 2, base_index + 3]
                     ]);
                 }
+                else if self.data[x][y].is_liquid {
+                    // a partly filled cell only covers its lower part, unless the same liquid is right above it
+                    let full = y+1 < CHUNK_HEIGHT && self.data[x][y+1].id == self.data[x][y].id;
+                    let fill = if full { 1.0 } else { self.data[x][y].level as f32 / MAX_LIQUID_LEVEL as f32 };
+
+                    liquid_vertices.extend([
+                        [x as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX * fill, 0.5],
+                        [x as f32 * BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX * fill, 0.5],
+                        [x as f32 * BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX, 0.5],
+                        [x as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX, 0.5],
+                    ]);
+
+                    let color = self.data[x][y].block_light.to_color(1.0);
+                    liquid_colors.extend([color; 4]);
+                    liquid_sky.extend([self.data[x][y].sky_brightness(1.0); 4]);
+
+                    let uv = atlas.tile_uv(self.data[x][y].tile);
+                    let uv_top = uv.min.y + uv.height() * (1. - fill);
+
+                    liquid_uvs.extend([
+                        [uv.min.x, uv_top],
+                        [uv.max.x, uv_top],
+                        [uv.max.x, uv.max.y],
+                        [uv.min.x, uv.max.y],
+                    ]);
+
+                    let base_index = liquid_vertices.len() as u32 - 4;
+                    liquid_indices.extend([
+                        base_index, base_index + 1, base_index + 2,
+                        base_index, base_index + 2, base_index + 3
+                    ]);
+                }
                 else if self.data[x][y].id != 0 {
                     not_solid_vertices.extend([
                         [x as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, -0.5],
@@ -126,18 +195,17 @@ impl Chunk {
                         [x as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX, -0.5],
                     ]);
 
-                    let light = self.data[x][y].light as f32 / 15.;
-                    let color = [light, light, light, 1.0];
+                    let color = self.data[x][y].block_light.to_color(1.0);
                     not_solid_colors.extend([color; 4]);
+                    not_solid_sky.extend([self.data[x][y].sky_brightness(1.0); 4]);
 
-                    let uv_offset_x = (self.data[x][y].id as f32 * BLOCK_SIZE_PX) / 256.;
-                    let uv_offset_y = ((self.data[x][y].id / 16) as f32 * 16. * BLOCK_SIZE_PX) / 256.;
+                    let uv = atlas.tile_uv(self.data[x][y].tile);
 
                     not_solid_uvs.extend([
-                        [uv_offset_x, uv_offset_y],
-                        [uv_offset_x + uv_block_size, uv_offset_y],
-                        [uv_offset_x + uv_block_size, uv_offset_y + uv_block_size],
-                        [uv_offset_x, uv_offset_y + uv_block_size],
+                        [uv.min.x, uv.min.y],
+                        [uv.max.x, uv.min.y],
+                        [uv.max.x, uv.max.y],
+                        [uv.min.x, uv.max.y],
                     ]);
 
                     let base_index = not_solid_vertices.len() as u32 - 4;
@@ -154,18 +222,17 @@ impl Chunk {
                         [x as f32 * BLOCK_SIZE_PX + BLOCK_SIZE_PX, y as f32 * BLOCK_SIZE_PX, -1.0],
                     ]);
 
-                    let light = self.background_data[x][y].light as f32 / 15.;
-                    let color = [light/10., light/10., light/10., 1.0];
+                    let color = self.background_data[x][y].block_light.to_color(0.1);
                     bg_colors.extend([color; 4]);
+                    bg_sky.extend([self.background_data[x][y].sky_brightness(0.1); 4]);
 
-                    let uv_offset_x = (self.background_data[x][y].id as f32 * BLOCK_SIZE_PX) / 256.;
-                    let uv_offset_y = ((self.background_data[x][y].id / 16) as f32 * 16. * BLOCK_SIZE_PX) / 256.;
+                    let uv = atlas.tile_uv(self.background_data[x][y].tile);
 
                     bg_uvs.extend([
-                        [uv_offset_x, uv_offset_y],
-                        [uv_offset_x + uv_block_size, uv_offset_y],
-                        [uv_offset_x + uv_block_size, uv_offset_y + uv_block_size],
-                        [uv_offset_x, uv_offset_y + uv_block_size],
+                        [uv.min.x, uv.min.y],
+                        [uv.max.x, uv.min.y],
+                        [uv.max.x, uv.max.y],
+                        [uv.min.x, uv.max.y],
                     ]);
 
                     let base_index = bg_vertices.len() as u32 - 4;
@@ -180,22 +247,32 @@ impl Chunk {
         let mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD)
             .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
             .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+            .with_inserted_attribute(ATTRIBUTE_SKY_LIGHT, sky)
             .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
             .with_inserted_indices(Indices::U32(indices));
 
         let not_solid_mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD)
             .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, not_solid_vertices)
             .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, not_solid_colors)
+            .with_inserted_attribute(ATTRIBUTE_SKY_LIGHT, not_solid_sky)
             .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, not_solid_uvs)
             .with_inserted_indices(Indices::U32(not_solid_indices));
 
         let bg_mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD)
             .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, bg_vertices)
             .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, bg_colors)
+            .with_inserted_attribute(ATTRIBUTE_SKY_LIGHT, bg_sky)
             .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, bg_uvs)
             .with_inserted_indices(Indices::U32(bg_indices));
 
-        (mesh, not_solid_mesh, bg_mesh, Collider::trimesh(collider_vertices, collider_indices))
+        let liquid_mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, liquid_vertices)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, liquid_colors)
+            .with_inserted_attribute(ATTRIBUTE_SKY_LIGHT, liquid_sky)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, liquid_uvs)
+            .with_inserted_indices(Indices::U32(liquid_indices));
+
+        (mesh, not_solid_mesh, bg_mesh, liquid_mesh, Collider::trimesh(collider_vertices, collider_indices))
     }
 }
 
@@ -204,311 +281,157 @@ pub struct GenerateChunkData {
     pub position: (i32, i32),
 }
 
-fn generate_chunk_data(
+pub(super) fn generate_chunk_data(
     mut ev_generate: EventReader<GenerateChunkData>,
     mut ev_update_light: EventWriter<UpdateChunkLight>,
     mut world: ResMut<super::World>,
     block_database: Res<BlockDatabase>,
+    world_save: Res<WorldSave>,
+    world_seed: Res<WorldSeed>,
+    pipeline: Res<WorldGenPipeline>,
 ) {
     for ev in ev_generate.read() {
         let (_x, _y) = ev.position;
-        let mut chunk = Chunk::new(_x);
-
-        let mut hasher = std::hash::DefaultHasher::new();
-        SEED.hash(&mut hasher);
-        let seed = hasher.finish() as u32;
-        let simplex = Simplex::new(seed);
-        let perlin = Perlin::new(seed);
-
-        let mut block_structures: Vec<((usize, usize), BlockStructure)> = vec![];
-
-        for y in 0..CHUNK_HEIGHT {
-            for x in 0..CHUNK_WIDTH {
 
-                let xf = x as f64 + _x as f64 * CHUNK_WIDTH as f64;
-                let yf = y as f64 + _y as f64 * CHUNK_HEIGHT as f64;
+        let (mut chunk, block_structures) = pipeline.generate(ev.position, world_seed.hash(), &block_database);
+
+        // a saved chunk already has its own structures and whatever spilled into it, but its structures are still
+        // placed to spill into the neighbours again
+        let restored = world_save.get_chunk(ev.position).is_some();
+        if let Some(saved_chunk) = world_save.get_chunk(ev.position) {
+            let open_sky = chunk.open_sky;
+            chunk = saved_chunk.restore(ev.position, &block_database);
+            // the save only has blocks, whether the sky is open comes from the terrain it was generated from
+            chunk.open_sky = open_sky;
+            world.modified_chunks.insert(ev.position);
+        }
 
-                let spread = 0.05;
-                let oct1 = perlin.get([xf * spread]);
-                let oct2 = perlin.get([xf * spread * 0.25]);
-                let oct3 = perlin.get([xf * spread * 2.]);
-                let height = (oct1+oct2+oct3 + CHUNK_HEIGHT as f64 / 2.).floor();
+        let mut touched_chunks = vec![];
 
-                let oct1 = simplex.get([xf * spread, yf * spread]);
-                let oct2 = simplex.get([xf * spread * 0.25, yf * spread * 0.25]);
-                let oct3 = simplex.get([xf * spread * 1.5, yf * spread * 1.5]);
-                let oct4 = simplex.get([xf * spread * 2.5, yf * spread * 2.5]);
-                let cave_density = oct1+oct2+oct3+oct4;
+        for ((x, y), structure) in block_structures.iter() {
+            for j in 0..structure.height() {
+                for i in 0..structure.width() {
+                    let block_id = structure.data[j][i];
+                    let bg_block_id = structure.bg_data[j][i];
+                    let air_fill = structure.air_mask[j][i];
 
-                let offset = 1000.0;
-                let oct1 = simplex.get([(xf + offset) * spread, (yf + offset) * spread]);
-                let oct2 = simplex.get([(xf + offset) * spread * 0.25, (yf + offset) * spread * 0.25]);
-                let oct3 = simplex.get([(xf + offset) * spread * 1.5, (yf + offset) * spread * 1.5]);
-                let block_density = oct1+oct2+oct3;
+                    if block_id == 0 && bg_block_id == 0 && !air_fill { continue; };
 
-                if yf < height {
+                    let world_x = _x * CHUNK_WIDTH as i32 + (x + i) as i32 - structure.anchor.0 as i32;
+                    let world_y = _y * CHUNK_HEIGHT as i32 + (y + j) as i32 - structure.anchor.1 as i32;
+                    let target = (world_x.div_euclid(CHUNK_WIDTH as i32), world_y.div_euclid(CHUNK_HEIGHT as i32));
 
-                    let density_check;
+                    let structure_block = StructureBlock {
+                        x: world_x.rem_euclid(CHUNK_WIDTH as i32) as usize,
+                        y: world_y.rem_euclid(CHUNK_HEIGHT as i32) as usize,
+                        block: block_database.get_by_id(block_id),
+                        bg_block: block_database.get_by_id(bg_block_id),
+                        air_fill,
+                    };
 
-                    if yf > height/3. {
-                        density_check = -0.9;
-                    }
-                    else {
-                        density_check = -0.1;
+                    if target == ev.position {
+                        if !restored {
+                            structure_block.apply(&mut chunk);
+                        }
+                        continue;
                     }
 
-                    if block_density > density_check {
-                        chunk.background_data[x][y] = block_database.get_by_id(1); // dirt
-                    }
-                    else {
-                        chunk.background_data[x][y] = block_database.get_by_id(3); // stone
-                    }                    
-
-                    if cave_density > density_check {
-                        if block_density > density_check {
-                            chunk.data[x][y] = block_database.get_by_id(1); // dirt
-                            continue;
-                        }
-                        
-                        chunk.data[x][y] = block_database.get_by_id(3); // stone
+                    // kept while this chunk is loaded, so the part comes back if the neighbour is unloaded and regenerated
+                    let pending = world.pending_structures.entry(target).or_default();
+                    let from_this_chunk = pending.entry(ev.position).or_default();
+                    if !touched_chunks.contains(&target) {
+                        from_this_chunk.clear();
+                        touched_chunks.push(target);
                     }
+                    from_this_chunk.push(structure_block);
                 }
+            }
+        }
 
-                // grass
-                if yf == height
-                && cave_density > -0.8{
-                    chunk.data[x][y] = block_database.get_by_id(2);
-                    chunk.background_data[x][y] = block_database.get_by_id(1); // dirt
+        // parts of structures from neighbours that were generated first
+        if !restored {
+            if let Some(pending) = world.pending_structures.get(&ev.position) {
+                apply_pending_structures(pending, &mut chunk);
+            }
+        }
 
-                    let mut rng = rand::thread_rng();
+        for target in touched_chunks {
+            // saved chunks already contain everything that spilled into them
+            if world.is_modified(target) { continue; }
 
-                    if x & 2 == 0 {
-                        chunk.data[x][y+1] = block_database.get_by_id(6); // thread
-                    }
+            let Some(pending) = world.pending_structures.get(&target).cloned() else { continue };
+            let Some(neighbour) = world.get_chunk_mut(target) else { continue };
 
-                    // trees
-                    if x % 9 == 0 {
-                        let structure = BlockStructure::new_tree(rng.gen_range(2..6));
-                        block_structures.push(((x,y), structure));
-                    }
-                }
-            }
+            apply_pending_structures(&pending, neighbour);
+            ev_update_light.send(UpdateChunkLight { position: target });
         }
 
-        for ((x, y), structure) in block_structures.iter() {
-            for j in 0..structure.height() {
-                for i in 0..structure.width() {
-                    let block_id = structure.data[j][i];
-                    let bg_block_id = structure.bg_data[j][i];
-    
-                    if block_id == 0 && !structure.fill_air { continue; };
-                    // todo: make it not to fill bg aswell
-
-                    if x+i < CHUNK_WIDTH {
-                        if y+j < CHUNK_HEIGHT {
-                            chunk.data[x+i][y+j] = block_database.get_by_id(block_id);
-                            chunk.background_data[x+i][y+j] = block_database.get_by_id(bg_block_id);
-                        }
-                        else {
-                            if let Some(neighbour) = world.get_chunk_mut(_x) {
-                                neighbour.data[x+i][j] = block_database.get_by_id(block_id);
-                                chunk.background_data[x+i][j] = block_database.get_by_id(bg_block_id);
-                                ev_update_light.send(UpdateChunkLight { position: _x });
-                            }
-                        }
-                    }
-                    else {
-                        if y+j < CHUNK_HEIGHT {
-                            if let Some(neighbour) = world.get_chunk_mut(_x+1) {
-                                neighbour.data[i][y+j] = block_database.get_by_id(block_id);
-                                chunk.background_data[i][y+j] = block_database.get_by_id(bg_block_id);
-                                ev_update_light.send(UpdateChunkLight { position: _x+1 });
-
-                            }
-                        }
-                        else {
-                            if let Some(neighbour) = world.get_chunk_mut(_x+1) {
-                                neighbour.data[i][j] = block_database.get_by_id(block_id);
-                                chunk.background_data[i][j] = block_database.get_by_id(bg_block_id);
-                                ev_update_light.send(UpdateChunkLight { position: _x+1 });
+        world.chunks.insert(ev.position, chunk);
+        ev_update_light.send(UpdateChunkLight { position: ev.position });
+    }
+}
 
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Places the parts that spilled into a chunk over its own structures, by the position of the chunk they came from.
+/// Every part is placed again when another one arrives, so the result doesn't depend on which chunk generated first
+fn apply_pending_structures(pending: &HashMap<(i32, i32), Vec<StructureBlock>>, chunk: &mut Chunk) {
+    let mut sources: Vec<&(i32, i32)> = pending.keys().collect();
+    sources.sort();
 
-        world.chunks.insert(_x, chunk);
-        ev_update_light.send(UpdateChunkLight { position: _x });
+    for source in sources {
+        for structure_block in pending[source].iter() {
+            structure_block.apply(chunk);
+        }
     }
 }
 
 #[derive(Event)]
 pub struct UpdateChunkLight {
-    pub position: i32
+    pub position: (i32, i32)
 }
 
+/// Chunks are small enough that relighting a single one per frame would keep a freshly streamed area dark for too long
+const LIGHT_UPDATES_PER_FRAME: usize = 8;
+
 #[derive(Resource)]
-pub struct LightUpdateQueue(pub VecDeque<(i32, bool)>);
+pub struct LightUpdateQueue(pub VecDeque<(i32, i32)>);
+
+impl LightUpdateQueue {
+    /// An update that is still waiting already sees every change made before it runs, so it isn't queued twice
+    fn push(&mut self, position: (i32, i32)) {
+        if !self.0.contains(&position) {
+            self.0.push_back(position);
+        }
+    }
+}
 
 fn push_light_updates(
     mut ev_update_light: EventReader<UpdateChunkLight>,
     mut queue: ResMut<LightUpdateQueue>,
-    world: Res<super::World>,
 ) {
     for ev in ev_update_light.read() {
-        let x = ev.position;
-
-        // middle
-        queue.0.push_back((x, true));
-
-        // sides
-        if world.get_chunk(x+1).is_some() {
-            queue.0.push_back((x+1, true));
-        }
-        if world.get_chunk(x-1).is_some() {
-            queue.0.push_back((x-1, true));
-        }  
-
-        // middle
-        queue.0.push_back((x, false));
-
-        // sides
-        if world.get_chunk(x+1).is_some() {
-            queue.0.push_back((x+1, false));
-        }
-        if world.get_chunk(x-1).is_some() {
-            queue.0.push_back((x-1, false));
-        }  
+        queue.push(ev.position);
     }
 }
 
+/// Light spreads on into the neighbours of a chunk, and back out of them, so every chunk it reached is redrawn
 fn update_light(
     mut world: ResMut<super::World>,
     mut ev_draw_chunk: EventWriter<DrawChunk>,
     mut queue: ResMut<LightUpdateQueue>,
 ) {
-    if let Some((position, internal)) = queue.0.pop_front() {
-        let _x = position; 
-        let mut block_light_queue = vec![];
-        let mut sun_light_queue = vec![];
-
-        let default_chunk = Chunk::PLACEHOLDER;
-        let mut chunk = world.get_chunk(_x).unwrap_or(&default_chunk).clone();
-        
-        if internal {
-            // sun light
-            for x in 0..CHUNK_WIDTH {
-                chunk.data[x][CHUNK_HEIGHT-1].light = 15;
-                sun_light_queue.push(((x,CHUNK_HEIGHT-1), 15));
-            }
+    let mut touched = HashSet::new();
 
-            for y in 0..CHUNK_HEIGHT {
-                for x in 0..CHUNK_WIDTH {                
-                    chunk.data[x][y].light = 0;
-                    chunk.background_data[x][y].light = 0;
-    
-                    if chunk.data[x][y].light_emission > 0 {
-                        chunk.data[x][y].light = chunk.data[x][y].light_emission;
-                        chunk.background_data[x][y].light = chunk.data[x][y].light_emission;
-                        block_light_queue.push(((x,y), chunk.data[x][y].light_emission));
-                    }
-                }
-            }
-        }
-        else {
-            let left_chunk = world.get_chunk(_x-1).unwrap_or(&default_chunk);
-            let right_chunk = world.get_chunk(_x+1).unwrap_or(&default_chunk);
+    for _ in 0..LIGHT_UPDATES_PER_FRAME {
+        let Some(position) = queue.0.pop_front() else { break };
+        // the chunk might have been unloaded while its update was queued
+        if world.get_chunk(position).is_none() { continue; }
 
-            for y in 0..CHUNK_HEIGHT {
-                if left_chunk.data[CHUNK_WIDTH-1][y].light > chunk.data[0][y].light {
-                    block_light_queue.push(((0,y), left_chunk.data[CHUNK_WIDTH-1][y].light - 1));
-                }
-                if right_chunk.data[0][y].light > chunk.data[CHUNK_WIDTH-1][y].light {
-                    block_light_queue.push(((CHUNK_WIDTH-1,y), right_chunk.data[0][y].light - 1));
-                }
-            }
-        };
-
-        while !sun_light_queue.is_empty() {
-            if let Some(((x, y), emission)) = sun_light_queue.pop() {
-                if emission >= 3 {
-                    if y > 0 {
-                        let emission = if chunk.data[x][y-1].is_solid {
-                            emission - 3
-                        } else { emission };
-
-                        if chunk.data[x][y-1].light < emission {
-                            chunk.data[x][y-1].light = emission;
-                            chunk.background_data[x][y-1].light = emission;
-                            sun_light_queue.push(((x,y-1), emission));
-                        }
-                    }
-
-                    if x+1 < CHUNK_WIDTH {
-                        if chunk.data[x+1][y].light < emission - 3 {
-                            chunk.data[x+1][y].light = emission - 3;
-                            chunk.background_data[x+1][y].light = emission - 3;
-                            sun_light_queue.push(((x+1,y), emission - 3));
-                        }
-                    }
-
-                    if x > 0 {
-                        if chunk.data[x-1][y].light < emission - 3 {
-                            chunk.data[x-1][y].light = emission - 3;
-                            chunk.background_data[x-1][y].light = emission - 3;
-                            sun_light_queue.push(((x-1,y), emission - 3));
-                        }
-                    }
-                }
-            }
-        }
-
-        while !block_light_queue.is_empty() {
-            if let Some(((x, y), emission)) = block_light_queue.pop() {
-                if emission >= 1 {
-                    if x+1 < CHUNK_WIDTH {
-                        if chunk.data[x+1][y].light < emission-1 {
-                            chunk.data[x+1][y].light = emission-1;
-                            chunk.background_data[x+1][y].light = emission-1;
-                            block_light_queue.push(((x+1,y), emission-1));
-                        }
-                    }
-    
-                    if y+1 < CHUNK_HEIGHT {
-                        if chunk.data[x][y+1].light < emission-1 {
-                            chunk.data[x][y+1].light = emission-1;
-                            chunk.background_data[x][y+1].light = emission-1;
-                            block_light_queue.push(((x,y+1), emission-1));
-                        }
-                    }
-
-                    if x > 0 {
-                        if chunk.data[x-1][y].light < emission-1 {
-                            chunk.data[x-1][y].light = emission-1;
-                            chunk.background_data[x-1][y].light = emission-1;
-                            block_light_queue.push(((x-1,y), emission-1));
-                        }
-                    }
+        relight_chunk(&mut world, position, &mut touched);
+    }
 
-                    if y > 0 {
-                        if chunk.data[x][y-1].light < emission-1 {
-                            chunk.data[x][y-1].light = emission-1;
-                            chunk.background_data[x][y-1].light = emission-1;
-                            block_light_queue.push(((x,y-1), emission-1));
-                        }
-                    }
-                }
-            }
-        }
-    
-        let chunk_to_edit= world.get_chunk_mut(_x).unwrap();
-        chunk_to_edit.data = chunk.data;
-        chunk_to_edit.background_data = chunk.background_data;
-        
-        if !internal {
-            ev_draw_chunk.send(DrawChunk { chunk });
+    for position in touched {
+        if let Some(chunk) = world.get_chunk(position) {
+            ev_draw_chunk.send(DrawChunk { chunk: *chunk });
         }
     }
 }
@@ -521,32 +444,39 @@ pub struct DrawChunk {
 fn draw_chunk(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    asset_server: Res<AssetServer>,
+    material: Res<ChunkMaterialHandle>,
+    atlas: Res<BlockAtlas>,
     mut ev_draw_chunk: EventReader<DrawChunk>,
     mut world: ResMut<super::World>,
 ) {
     for ev in ev_draw_chunk.read() {
-        let (mesh, not_solid_mesh, bg_mesh, collider) = ev.chunk.create_mesh();
+        if world.get_chunk(ev.chunk.position).is_none() { continue; }
+        let (x, y) = ev.chunk.position;
+
+        let (mesh, not_solid_mesh, bg_mesh, liquid_mesh, collider) = ev.chunk.create_mesh(&atlas);
 
         let chunk_entity = commands.spawn((
             Mesh2d(meshes.add(mesh)),
-            MeshMaterial2d(materials.add(asset_server.load("blocks.png"))),
+            MeshMaterial2d(material.0.clone()),
             Transform::from_translation(Vec3::new(
-                ev.chunk.position as f32 * CHUNK_WIDTH as f32 * BLOCK_SIZE_PX,0.0, 0.0
+                x as f32 * CHUNK_WIDTH as f32 * BLOCK_SIZE_PX, y as f32 * CHUNK_HEIGHT as f32 * BLOCK_SIZE_PX, 0.0
             )),
             collider,
-            CollisionGroups::new(Group::GROUP_2, Group::GROUP_1 | Group::GROUP_3),
+            CollisionGroups::new(Group::GROUP_2, Group::GROUP_1 | Group::GROUP_3 | Group::GROUP_4),
             Friction::coefficient(0.0),
             Restitution::coefficient(0.0),
         ))
         .with_child((
             Mesh2d(meshes.add(not_solid_mesh)),
-            MeshMaterial2d(materials.add(asset_server.load("blocks.png"))), 
+            MeshMaterial2d(material.0.clone()),
+        ))
+        .with_child((
+            Mesh2d(meshes.add(liquid_mesh)),
+            MeshMaterial2d(material.0.clone()),
         ))
         .with_child((
             Mesh2d(meshes.add(bg_mesh)),
-            MeshMaterial2d(materials.add(asset_server.load("blocks.png"))),
+            MeshMaterial2d(material.0.clone()),
             Transform::from_translation(Vec3::new(
                 0.0, 0.0, -1.0
             )),
@@ -559,4 +489,115 @@ fn draw_chunk(
         }
         world.chunk_entites.insert(ev.chunk.position, chunk_entity);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::{pack::ContentPacks, registry::build_registries, world::{self, generation::load_pipeline}};
+
+    const POSITIONS: [(i32, i32); 6] = [(0, 4), (1, 4), (-3, 4), (7, 3), (0, 2), (-5, 1)];
+
+    /// Foreground and background block ids of every cell
+    fn block_ids(chunk: &Chunk) -> Vec<u32> {
+        chunk.data.iter().chain(chunk.background_data.iter()).flatten().map(|block| block.id).collect()
+    }
+
+    /// FNV-1a over the block ids, it has to stay the same across platforms and compiler versions
+    fn fingerprint(ids: &[u32]) -> u64 {
+        ids.iter().flat_map(|id| id.to_le_bytes()).fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    /// Runs `generate_chunk_data` for `positions` in order, in an app of its own, with only the base assets
+    /// so packs lying around in `packs` don't change the result
+    fn generate_world(seed: &str, positions: &[(i32, i32)]) -> world::World {
+        let seed = WorldSeed(seed.to_string());
+        let packs = ContentPacks::base();
+        let read = |path: &Path| fs::read_to_string(path).map_err(|err| err.to_string());
+        let Ok((block_database, _)) = build_registries(&packs, &read) else { panic!("the base block data has problems") };
+        let pipeline = load_pipeline(&packs).expect("the base world generation data has problems");
+
+        let mut app = App::new();
+        app
+            .add_event::<GenerateChunkData>()
+            .add_event::<UpdateChunkLight>()
+            .init_resource::<world::World>()
+            .insert_resource(WorldSave::new(&seed))
+            .insert_resource(seed)
+            .insert_resource(block_database)
+            .insert_resource(pipeline)
+            .add_systems(Update, generate_chunk_data);
+
+        for &position in positions {
+            app.world_mut().send_event(GenerateChunkData { position });
+        }
+        app.update();
+
+        app.world_mut().remove_resource::<world::World>().unwrap()
+    }
+
+    /// Block ids of the chunks at `POSITIONS`
+    fn generate(seed: &str) -> Vec<Vec<u32>> {
+        let world = generate_world(seed, &POSITIONS);
+        POSITIONS.iter().map(|&position| block_ids(world.get_chunk(position).unwrap())).collect()
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_chunks() {
+        let first = generate("rustaria");
+        let second = generate("rustaria");
+
+        for (position, (first, second)) in POSITIONS.iter().zip(first.iter().zip(second.iter())) {
+            assert!(first == second, "chunk {position:?} came out different");
+        }
+        // generation that ignores the chunk position would make every chunk the same
+        assert_ne!(first[0], first[1]);
+    }
+
+    #[test]
+    fn different_seeds_generate_different_chunks() {
+        let first = generate("rustaria");
+        let second = generate("terraria");
+
+        assert_ne!(first[0], second[0]);
+        assert_ne!(first[1], second[1]);
+    }
+
+    /// Fingerprints of `POSITIONS` for the seed "rustaria". They only change when generation does on purpose,
+    /// then they have to be updated from the failure message
+    const PINNED: [u64; 6] = [
+        1531479585861479428, 1115613030192993153, 15061562560117971781,
+        624027662617745003, 15885949008993965119, 12090089206962249900,
+    ];
+
+    #[test]
+    fn seed_generates_the_pinned_chunks() {
+        let fingerprints: Vec<u64> = generate("rustaria").iter().map(|ids| fingerprint(ids)).collect();
+        assert_eq!(fingerprints, PINNED, "generation changed, the chunks of {POSITIONS:?} came out different");
+    }
+
+    #[test]
+    fn structures_spill_the_same_whichever_chunk_generates_first() {
+        // the surface runs along the border of rows 3 and 4 here, and trees there reach across it and sideways
+        let positions: Vec<(i32, i32)> = (4..8).flat_map(|x| [(x, 3), (x, 4)]).collect();
+        let reversed: Vec<(i32, i32)> = positions.iter().rev().copied().collect();
+
+        let forward = generate_world("rustaria", &positions);
+        let backward = generate_world("rustaria", &reversed);
+
+        let spilled = |world: &world::World| positions.iter()
+            .filter(|position| world.pending_structures.get(position).is_some_and(|pending| !pending.is_empty()))
+            .count();
+        // without a structure crossing between the generated chunks there would be nothing to compare
+        assert!(spilled(&forward) > 0);
+
+        for position in positions.iter() {
+            let (first, second) = (forward.get_chunk(*position).unwrap(), backward.get_chunk(*position).unwrap());
+            assert!(block_ids(first) == block_ids(second), "chunk {position:?} depends on the generation order");
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs;
+
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{CompressedImageFormats, ImageSampler, ImageType},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat}
+};
+
+use crate::{pack::{ContentPack, ContentPacks}, registry::RegistryError, BLOCK_SIZE_PX};
+
+/// Block textures of a pack, a grid of `ATLAS_TILES` by `ATLAS_TILES` tiles that blocks pick theirs from with `tile`
+pub const ATLAS_FILE: &str = "blocks.png";
+pub const ATLAS_TILES: u32 = 16;
+
+const PAGE_SIZE_PX: u32 = ATLAS_TILES * BLOCK_SIZE_PX as u32;
+
+/// The blocks.png of every pack that has one, stacked top to bottom in load order. Each pack's atlas is a page,
+/// so packs add block textures instead of replacing each other's
+#[derive(Resource)]
+pub struct BlockAtlas {
+    pub image: Handle<Image>,
+    pages: u32,
+}
+
+impl BlockAtlas {
+    /// Page of `pack`'s blocks.png, `None` if it has none
+    pub fn page(packs: &ContentPacks, pack: &ContentPack) -> Option<u32> {
+        pack.file(ATLAS_FILE)?;
+        Some(packs.iter().filter(|other| other.file(ATLAS_FILE).is_some()).take_while(|other| other.id != pack.id).count() as u32)
+    }
+
+    /// Tile of a block, counted across every page
+    pub fn tile(page: u32, column: u32, row: u32) -> u32 {
+        (page * ATLAS_TILES + row) * ATLAS_TILES + column
+    }
+
+    /// Area of a tile in pixels, like a sprite rect
+    pub fn tile_rect(&self, tile: u32) -> Rect {
+        let corner = Vec2::new((tile % ATLAS_TILES) as f32, (tile / ATLAS_TILES) as f32) * BLOCK_SIZE_PX;
+        Rect::from_corners(corner, corner + BLOCK_SIZE_PX)
+    }
+
+    /// Area of a tile in uv coordinates, from 0 to 1
+    pub fn tile_uv(&self, tile: u32) -> Rect {
+        let size = Vec2::new(PAGE_SIZE_PX as f32, (PAGE_SIZE_PX * self.pages.max(1)) as f32);
+        let rect = self.tile_rect(tile);
+        Rect::from_corners(rect.min / size, rect.max / size)
+    }
+
+    /// Stacks the pages into one image. Every blocks.png has to be `PAGE_SIZE_PX` square
+    pub fn build(packs: &ContentPacks, images: &mut Assets<Image>) -> Result<Self, Vec<RegistryError>> {
+        let mut data = vec![];
+        let mut pages = 0;
+        let mut errors = vec![];
+
+        for (_, path) in packs.files(ATLAS_FILE) {
+            let file = path.to_string_lossy();
+            let image = fs::read(&path).map_err(|err| err.to_string()).and_then(|bytes| {
+                Image::from_buffer(
+                    &bytes,
+                    ImageType::Extension("png"),
+                    CompressedImageFormats::NONE,
+                    true,
+                    ImageSampler::Default,
+                    RenderAssetUsages::RENDER_WORLD,
+                ).map_err(|err| err.to_string())
+            });
+
+            match image.map(|image| image.convert(TextureFormat::Rgba8UnormSrgb)) {
+                Ok(Some(image)) if image.width() == PAGE_SIZE_PX && image.height() == PAGE_SIZE_PX => {
+                    data.extend(image.data);
+                    pages += 1;
+                },
+                Ok(Some(image)) => errors.push(RegistryError::new(&file, "", format!(
+                    "is {}x{}, has to be {PAGE_SIZE_PX}x{PAGE_SIZE_PX}", image.width(), image.height()
+                ))),
+                Ok(None) => errors.push(RegistryError::new(&file, "", "has a pixel format that can't be converted")),
+                Err(err) => errors.push(RegistryError::new(&file, "", format!("can't be read: {err}"))),
+            }
+        }
+
+        if pages == 0 {
+            errors.push(RegistryError::new(ATLAS_FILE, "", "isn't in any pack"));
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let image = Image::new(
+            Extent3d { width: PAGE_SIZE_PX, height: PAGE_SIZE_PX * pages, depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+
+        Ok(Self { image: images.add(image), pages })
+    }
+}
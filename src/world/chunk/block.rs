@@ -1,82 +1,258 @@
-use std::{fs::File, io::Read};
+use std::collections::HashMap;
 
 use bevy::prelude::*;
 use serde::Deserialize;
-use serde_json::Value;
 
-pub struct BlockPlugin;
+use crate::{inventory::item::Tool, registry::qualify};
 
-impl Plugin for BlockPlugin {
-    fn build(&self, app: &mut App) {
-        app.add_systems(Startup, init_block_database);
-    }
-}
-
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Default)]
 pub enum BlockLayer {
     Background,
+    #[default]
     Foreground,
 }
 
-#[allow(unused)]
 pub const MAX_LIGHT_LEVEL: u8 = 15;
 
+/// Light opacity of solid blocks that don't set their own
+const SOLID_LIGHT_OPACITY: u8 = 3;
+
+/// Light level of every color channel, from 0 to `MAX_LIGHT_LEVEL`. Light from different sources mixes by taking
+/// the brightest of each channel, so a red and a blue light make purple where they overlap.
+/// In block_data.json it's either one level for white light or `[red, green, blue]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Deserialize)]
+#[serde(from = "LightData")]
+pub struct Light {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LightData {
+    Gray(u8),
+    Rgb([u8; 3]),
+}
+
+impl From<LightData> for Light {
+    fn from(data: LightData) -> Self {
+        match data {
+            LightData::Gray(level) => Self::gray(level),
+            LightData::Rgb([r, g, b]) => Self::rgb(r, g, b),
+        }
+    }
+}
+
+impl Light {
+    pub const NONE: Light = Light::gray(0);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub const fn gray(level: u8) -> Self {
+        Self::rgb(level, level, level)
+    }
+
+    /// Level of the brightest channel, for anything that only cares how much light there is
+    pub fn level(self) -> u8 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    /// Vertex color, every channel scaled by `brightness`
+    pub fn to_color(self, brightness: f32) -> [f32; 4] {
+        let channel = |level: u8| level as f32 / MAX_LIGHT_LEVEL as f32 * brightness;
+        [channel(self.r), channel(self.g), channel(self.b), 1.0]
+    }
+}
+
+/// Fill level of a liquid block that takes up the whole cell
+pub const MAX_LIQUID_LEVEL: u8 = 8;
+
+/// What a block is made of, which decides the tools that are effective on it
+#[derive(Clone, Copy, Debug, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockCategory {
+    Soil,
+    Stone,
+    Ore,
+    Wood,
+    Plant,
+    #[default]
+    Other,
+}
+
+impl BlockCategory {
+    /// Bit of the category in a tool's effective categories
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Block {
     pub id: u32,
+    /// Texture in the `BlockAtlas`
+    pub tile: u32,
     pub is_solid: bool,
     pub durability: u8,
-    pub drop_item: u32,
-    pub light_emission: u8,
-    pub light: u8,
+    /// Item id dropped when there is no loot table for the block
+    pub drop_item: Option<u32>,
+    pub light_emission: Light,
+    /// Light levels lost at least by light going into the block. Air lets sun light fall without losing any,
+    /// `MAX_LIGHT_LEVEL` blocks all light
+    pub light_opacity: u8,
+    /// Light from the sky, which is always white. How bright it is depends on the time of day, which is applied when drawing
+    pub sky_light: u8,
+    /// Light from emitting blocks
+    pub block_light: Light,
+    pub is_liquid: bool,
+    /// Liquid ticks between two moves of a liquid, higher is slower
+    pub viscosity: u8,
+    /// How full a liquid block is, up to `MAX_LIQUID_LEVEL`
+    pub level: u8,
+    /// Falls down when there is nothing solid under it
+    pub has_gravity: bool,
+    pub category: BlockCategory,
+    /// Tier of an effective tool the block has to be broken with to drop anything, 0 if hands are enough
+    pub min_tool_tier: u8,
 }
 
 impl Block {
     pub const AIR: Block = Block {
         id: 0,
+        tile: 0,
         is_solid: false,
         durability: 0,
-        drop_item: 0,
-        light_emission: 0,
-        light: 0,
+        drop_item: None,
+        light_emission: Light::NONE,
+        light_opacity: 0,
+        sky_light: 0,
+        block_light: Light::NONE,
+        is_liquid: false,
+        viscosity: 0,
+        level: 0,
+        has_gravity: false,
+        category: BlockCategory::Other,
+        min_tool_tier: 0,
     };
-}
 
-#[derive(Resource, Deserialize)]
-pub struct BlockDatabase {
-    pub blocks: Vec<Value>,
+    /// The same block lit like `other`. A placed block keeps the light of the cell until its light is updated,
+    /// which needs to know what the cell was lit with before
+    pub fn lit_like(self, other: Block) -> Block {
+        Block { sky_light: other.sky_light, block_light: other.block_light, ..self }
+    }
+
+    /// Sky light vertex attribute, scaled by `brightness`. The shader multiplies it by the color of the sky
+    pub fn sky_brightness(&self, brightness: f32) -> f32 {
+        self.sky_light as f32 / MAX_LIGHT_LEVEL as f32 * brightness
+    }
+
+    /// Whether breaking the block with `tool`, or by hand for `None`, drops its item
+    pub fn drops_with(&self, tool: Option<Tool>) -> bool {
+        self.min_tool_tier == 0
+            || tool.is_some_and(|tool| tool.is_effective_on(self.category) && tool.tier >= self.min_tool_tier)
+    }
 }
 
-impl BlockDatabase {
-    pub fn get_by_id(&self, id: u32) -> Block {
-        let block_data = self.blocks[id as usize].as_object().unwrap();
+fn default_viscosity() -> u8 {
+    1
+}
 
-        let is_solid = block_data.get("is_solid").unwrap().as_bool().unwrap();
-        let durability = block_data.get("durability").unwrap().as_u64().unwrap() as u8;
-        let drop_item = block_data.get("drop_item").unwrap().as_u64().unwrap() as u32;
-        let light_emission = block_data.get("light_emission").unwrap().as_u64().unwrap() as u8;
+/// One entry of block_data.json
+#[derive(Deserialize)]
+pub struct BlockData {
+    /// Namespaced name like `rustaria:dirt`. The numeric id is assigned when the registry loads
+    pub id: String,
+    /// `[column, row]` of the texture in the blocks.png of the pack that adds the block. Only air goes without
+    #[serde(default)]
+    pub tile: Option<[u32; 2]>,
+    pub is_solid: bool,
+    pub durability: u8,
+    /// Name of the item, blocks without one drop nothing
+    #[serde(default)]
+    pub drop_item: Option<String>,
+    pub light_emission: Light,
+    /// Defaults to `SOLID_LIGHT_OPACITY` for solid blocks and 0 for everything else
+    #[serde(default)]
+    pub light_opacity: Option<u8>,
+    #[serde(default)]
+    pub is_liquid: bool,
+    #[serde(default = "default_viscosity")]
+    pub viscosity: u8,
+    #[serde(default, rename = "gravity")]
+    pub has_gravity: bool,
+    #[serde(default)]
+    pub category: BlockCategory,
+    #[serde(default)]
+    pub min_tool_tier: u8,
+    /// Tree species the block grows into, which makes it a sapling
+    #[serde(default)]
+    pub tree_species: Option<String>,
+}
 
+impl BlockData {
+    pub fn to_block(&self, id: u32, tile: u32, drop_item: Option<u32>) -> Block {
         Block {
             id,
-            is_solid,
-            durability,
+            tile,
+            is_solid: self.is_solid,
+            durability: self.durability,
             drop_item,
-            light_emission, 
-            light: 0,
+            light_emission: self.light_emission,
+            light_opacity: self.light_opacity
+                .unwrap_or(if self.is_solid { SOLID_LIGHT_OPACITY } else { 0 })
+                .min(MAX_LIGHT_LEVEL),
+            sky_light: 0,
+            block_light: Light::NONE,
+            is_liquid: self.is_liquid,
+            viscosity: self.viscosity,
+            level: if self.is_liquid { MAX_LIQUID_LEVEL } else { 0 },
+            has_gravity: self.has_gravity,
+            category: self.category,
+            min_tool_tier: self.min_tool_tier,
         }
     }
 }
 
-fn init_block_database(
-    mut commands: Commands,
-) {
-    let mut file = File::open("assets/block_data.json").unwrap();
+/// Every block, indexed by id. Built once by the registry, see `registry::load_registries`
+#[derive(Resource)]
+pub struct BlockDatabase {
+    blocks: Vec<Block>,
+    names: Vec<String>,
+    tree_species: Vec<Option<String>>,
+    ids: HashMap<String, u32>,
+}
 
-    let mut string = String::new();
-    let _ = file.read_to_string(&mut string);
+impl BlockDatabase {
+    /// `blocks[i]` has id `i`, is called `names[i]` and grows into `tree_species[i]`. Air has to come first
+    pub fn new(blocks: Vec<Block>, names: Vec<String>, tree_species: Vec<Option<String>>) -> Self {
+        let ids = names.iter().enumerate().map(|(id, name)| (name.clone(), id as u32)).collect();
+        Self { blocks, names, tree_species, ids }
+    }
 
-    let map = serde_json::from_str::<serde_json::Map<String, Value>>(&string).unwrap();
-    let blocks: Vec<Value> = map.get("blocks").unwrap().as_array().unwrap().to_vec();
+    pub fn get_by_id(&self, id: u32) -> Block {
+        self.blocks[id as usize]
+    }
+
+    /// Like `get_by_id`, but `None` for ids that aren't registered
+    pub fn get(&self, id: u32) -> Option<Block> {
+        self.blocks.get(id as usize).copied()
+    }
 
-    commands.insert_resource(BlockDatabase{blocks});
-}
\ No newline at end of file
+    /// Names without a namespace are looked up in the default one, so `dirt` finds `rustaria:dirt`
+    pub fn get_by_name(&self, name: &str) -> Option<Block> {
+        self.ids.get(&qualify(name)).map(|id| self.blocks[*id as usize])
+    }
+
+    /// Names of every block, in id order
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Tree species a sapling block grows into, `None` for every other block
+    pub fn tree_species(&self, id: u32) -> Option<&str> {
+        self.tree_species.get(id as usize)?.as_deref()
+    }
+}
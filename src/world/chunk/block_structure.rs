@@ -1,74 +1,171 @@
+use std::collections::HashMap;
+
+use bevy::log::warn;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::world::chunk::{block::{Block, BlockDatabase}, Chunk};
+
+/// Block ids of a structure, rows from the bottom up
 pub struct BlockStructure {
     pub data: Vec<Vec<u32>>,
     pub bg_data: Vec<Vec<u32>>,
-    pub fill_air: bool,
+    /// Cells where the structure replaces whatever is in the world, empty layers included
+    pub air_mask: Vec<Vec<bool>>,
+    /// Cell that goes on the placement point, counted from the bottom left
+    pub anchor: (usize, usize),
 }
 
 impl BlockStructure {
-    pub fn new_tree(logs: u32) -> Self {
-        // let mut tree = Tree::new();
-        // tree.grow();
-        // tree.generate_leaves();
-
-        // let mut data = vec![];
-
-        // for y in 0..tree.grid.len() {
-        //     let mut row = vec![];
-        //     for x in 0..tree.grid[0].len() {
-        //         let id = match tree.grid[x][y] {
-        //             Tile::Air => 0,
-        //             Tile::Leaves => 5,
-        //             _ => 4,
-        //         };
-        //         row.push(id as u32);
-        //     }
-        //     data.push(row);
-        // }
-
-        let mut data = vec![
-            vec![0, 5, 5, 5, 0],
-            vec![0, 5, 5, 5, 0],
-            vec![5, 5, 5, 5, 5],
-            vec![5, 5, 5, 5, 5],
-        ];
-        data.append(&mut vec![vec![0,0,4,0,0]; logs as usize]);
-
-        data.reverse();
-
-        Self {
-            data,
-            bg_data: vec![vec![0; 16]; 16],
-            fill_air: false,
+    pub fn height(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn width(&self) -> usize {
+        self.data.first().map_or(0, |row| row.len())
+    }
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+pub struct StructureVariant {
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Rows from the top down, one character per block
+    pub foreground: Vec<String>,
+    #[serde(default)]
+    pub background: Vec<String>,
+    /// Rows from the top down, `x` marks cells where empty cells of the layers clear the world too.
+    /// Both layers are cleared there unless the structure has a block in them
+    #[serde(default)]
+    pub air_fill: Vec<String>,
+}
+
+/// Structure layout loaded from `assets/structures/<name>.json`
+#[derive(Deserialize)]
+pub struct StructureTemplate {
+    /// Block names for the characters used in the layers, `.` always means an empty cell
+    pub palette: HashMap<char, String>,
+    /// Cell that goes on the placement point, counted from the bottom left
+    pub anchor: (usize, usize),
+    /// One of them is picked by weight every time the structure is placed
+    pub variants: Vec<StructureVariant>,
+}
+
+impl StructureTemplate {
+    /// Layouts that can't be placed, like a variant without a single cell
+    pub fn validate(self) -> Result<Self, String> {
+        for (index, variant) in self.variants.iter().enumerate() {
+            let has_cells = variant.foreground.iter().chain(variant.background.iter()).any(|row| !row.is_empty());
+            if !has_cells {
+                return Err(format!("variants[{index}] has no cells"));
+            }
         }
+        Ok(self)
     }
 
-    #[allow(unused)]
-    pub fn new_house() -> Self {
-        let mut data = vec![
-            vec![3,3,3,3,3,3],
-            vec![3,0,0,0,0,3],
-            vec![0,0,0,0,0,0],
-            vec![0,0,0,0,0,0],
-            vec![3,3,3,3,3,3],
-        ];
+    pub fn instantiate(&self, rng: &mut impl Rng, block_database: &BlockDatabase) -> Option<BlockStructure> {
+        let total: u32 = self.variants.iter().map(|variant| variant.weight).sum();
+        if total == 0 { return None; }
+
+        let mut roll = rng.gen_range(0..total);
+        let variant = self.variants.iter().find(|variant| {
+            if roll < variant.weight { return true; }
+            roll -= variant.weight;
+            false
+        })?;
 
-        let mut bg_data = vec![vec![3; 6]; 5];
+        let height = variant.foreground.len().max(variant.background.len());
+        let width = variant.foreground.iter()
+            .chain(variant.background.iter())
+            .map(|row| row.chars().count())
+            .max()?;
 
-        data.reverse();
-        bg_data.reverse();
+        let cell = |layer: &Vec<String>, x: usize, y: usize| -> char {
+            // layers are written top down and line up at the bottom, structures are stored bottom up
+            layer.len().checked_sub(y + 1)
+                .map(|row| &layer[row])
+                .and_then(|row| row.chars().nth(x))
+                .unwrap_or('.')
+        };
 
-        Self {
+        let block_id = |c: char| -> u32 {
+            if c == '.' { return 0; }
+
+            let Some(name) = self.palette.get(&c) else {
+                warn!("structure template uses '{c}' which is not in its palette");
+                return 0;
+            };
+            block_database.get_by_name(name).map(|block| block.id).unwrap_or_else(|| {
+                warn!("unknown block \"{name}\" in a structure template");
+                0
+            })
+        };
+
+        let mut data = vec![vec![0; width]; height];
+        let mut bg_data = vec![vec![0; width]; height];
+        let mut air_mask = vec![vec![false; width]; height];
+
+        for y in 0..height {
+            for x in 0..width {
+                data[y][x] = block_id(cell(&variant.foreground, x, y));
+                bg_data[y][x] = block_id(cell(&variant.background, x, y));
+                air_mask[y][x] = cell(&variant.air_fill, x, y) == 'x';
+            }
+        }
+
+        Some(BlockStructure {
             data,
             bg_data,
-            fill_air: true,
+            air_mask,
+            anchor: self.anchor,
+        })
+    }
+}
+
+/// One cell of a placed structure, in the chunk it landed in
+#[derive(Clone, Copy)]
+pub struct StructureBlock {
+    pub x: usize,
+    pub y: usize,
+    pub block: Block,
+    pub bg_block: Block,
+    /// Empty layers clear the cell too, see `StructureVariant::air_fill`
+    pub air_fill: bool,
+}
+
+impl StructureBlock {
+    /// Places the layers that have a block, and the empty ones too in air-fill cells
+    pub fn apply(&self, chunk: &mut Chunk) {
+        if self.block.id != 0 || self.air_fill {
+            chunk.data[self.x][self.y] = self.block;
+        }
+        if self.bg_block.id != 0 || self.air_fill {
+            chunk.background_data[self.x][self.y] = self.bg_block;
         }
     }
+}
 
-    pub fn height(&self) -> usize {
-        self.data.len()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(json: &str) -> Result<StructureTemplate, String> {
+        serde_json::from_str::<StructureTemplate>(json).map_err(|err| err.to_string()).and_then(StructureTemplate::validate)
     }
 
-    pub fn width(&self) -> usize {
-        self.data[0].len()
+    #[test]
+    fn variant_without_cells_is_rejected() {
+        let valid = template(r#"{ "palette": { "s": "rustaria:stone" }, "anchor": [0, 0], "variants": [{ "foreground": ["ss"] }] }"#);
+        assert!(valid.is_ok());
+
+        let empty = template(r#"{ "palette": {}, "anchor": [0, 0], "variants": [{ "foreground": ["."] }, { "foreground": [], "air_fill": ["x"] }] }"#);
+        assert_eq!(empty.err().as_deref(), Some("variants[1] has no cells"));
+
+        let blank = template(r#"{ "palette": {}, "anchor": [0, 0], "variants": [{ "foreground": [""], "background": [""] }] }"#);
+        assert_eq!(blank.err().as_deref(), Some("variants[0] has no cells"));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,375 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use super::{block::{Block, MAX_LIGHT_LEVEL}, DrawChunk};
+use crate::{world::World, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+/// Right, left, up and down
+const NEIGHBOURS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+const UP: (i32, i32) = (0, 1);
+const DOWN: (i32, i32) = (0, -1);
+
+/// Light levels sun light loses going to the sides, so the walls of a shaft are lit without the caves next to it
+const SKY_SIDE_FALLOFF: u8 = 3;
+
+/// Sent when a single block changed, its position in world blocks. Its light and everything lit through it is fixed up
+/// in the same frame, only as far as the light reaches
+#[derive(Event)]
+pub struct UpdateBlockLight {
+    pub position: (i32, i32),
+}
+
+/// Light that spreads on its own. The color channels of block light never mix while spreading,
+/// so each is updated by itself, like the sky light
+#[derive(Clone, Copy)]
+enum Channel {
+    Red,
+    Green,
+    Blue,
+    Sky,
+}
+
+impl Channel {
+    const ALL: [Channel; 4] = [Channel::Red, Channel::Green, Channel::Blue, Channel::Sky];
+
+    fn level(self, block: &Block) -> u8 {
+        match self {
+            Channel::Red => block.block_light.r,
+            Channel::Green => block.block_light.g,
+            Channel::Blue => block.block_light.b,
+            Channel::Sky => block.sky_light,
+        }
+    }
+
+    fn set_level(self, block: &mut Block, level: u8) {
+        match self {
+            Channel::Red => block.block_light.r = level,
+            Channel::Green => block.block_light.g = level,
+            Channel::Blue => block.block_light.b = level,
+            Channel::Sky => block.sky_light = level,
+        }
+    }
+
+    /// Light a cell gets from a neighbour at `level`, `direction` being the way the light travels. Block light loses
+    /// a level every step. Sun light falls straight down without losing any, loses more going to the sides and never
+    /// goes up. Either loses at least the opacity of the cell it goes into
+    fn spread(self, level: u8, direction: (i32, i32), target: &Block) -> u8 {
+        let falloff = match (self, direction) {
+            (Channel::Sky, DOWN) => 0,
+            (Channel::Sky, UP) => return 0,
+            (Channel::Sky, _) => SKY_SIDE_FALLOFF,
+            _ => 1,
+        };
+
+        level.saturating_sub(falloff.max(target.light_opacity))
+    }
+
+    /// Light a cell has no matter its neighbours. That's the emission of the block, and for the sky the top of
+    /// a chunk under open sky with nothing loaded above it, see `Chunk::open_sky`
+    fn source(self, world: &World, position: (i32, i32), block: &Block) -> u8 {
+        match self {
+            Channel::Red => block.light_emission.r,
+            Channel::Green => block.light_emission.g,
+            Channel::Blue => block.light_emission.b,
+            Channel::Sky => {
+                let ((chunk_x, chunk_y), (x, y)) = World::locate_block(position);
+                if y + 1 < CHUNK_HEIGHT || world.get_chunk((chunk_x, chunk_y+1)).is_some() { return 0; }
+                if !world.get_chunk((chunk_x, chunk_y)).is_some_and(|chunk| chunk.open_sky[x]) { return 0; }
+
+                self.spread(MAX_LIGHT_LEVEL, DOWN, block)
+            }
+        }
+    }
+}
+
+/// Light of the changed blocks being fixed up, and the chunks that were touched on the way
+struct Relight<'a> {
+    world: &'a mut World,
+    channel: Channel,
+    touched: &'a mut HashSet<(i32, i32)>,
+}
+
+impl Relight<'_> {
+    fn level(&self, position: (i32, i32)) -> Option<u8> {
+        self.world.get_block_at(position).map(|block| self.channel.level(&block))
+    }
+
+    fn set_level(&mut self, position: (i32, i32), level: u8) {
+        let (chunk_position, (x, y)) = World::locate_block(position);
+        let Some(chunk) = self.world.get_chunk_mut(chunk_position) else { return };
+
+        self.channel.set_level(&mut chunk.data[x][y], level);
+        self.channel.set_level(&mut chunk.background_data[x][y], level);
+        self.touched.insert(chunk_position);
+    }
+
+    /// Takes away every bit of light that came through the changed blocks, then lets the light around that is left
+    /// and the sources among the darkened cells spread back in
+    fn run(&mut self, changed: &[(i32, i32)]) {
+        let mut removal = VecDeque::new();
+        let mut darkened = vec![];
+        let mut spread = VecDeque::new();
+
+        for &position in changed {
+            let Some(level) = self.level(position) else { continue };
+            self.set_level(position, 0);
+            removal.push_back((position, level));
+        }
+
+        while let Some(((x, y), level)) = removal.pop_front() {
+            darkened.push((x, y));
+
+            for direction in NEIGHBOURS {
+                let neighbour = (x + direction.0, y + direction.1);
+                let Some(block) = self.world.get_block_at(neighbour) else { continue };
+                let neighbour_level = self.channel.level(&block);
+                if neighbour_level == 0 { continue; }
+
+                // anything this bright could have come from the darkened cell, anything brighter has another source
+                if neighbour_level <= self.channel.spread(level, direction, &block) {
+                    self.set_level(neighbour, 0);
+                    removal.push_back((neighbour, neighbour_level));
+                }
+                else {
+                    spread.push_back(neighbour);
+                }
+            }
+        }
+
+        for position in darkened {
+            let Some(block) = self.world.get_block_at(position) else { continue };
+            let source = self.channel.source(self.world, position, &block);
+
+            if source > self.channel.level(&block) {
+                self.set_level(position, source);
+                spread.push_back(position);
+            }
+        }
+
+        while let Some((x, y)) = spread.pop_front() {
+            let Some(level) = self.level((x, y)) else { continue };
+
+            for direction in NEIGHBOURS {
+                let neighbour = (x + direction.0, y + direction.1);
+                let Some(block) = self.world.get_block_at(neighbour) else { continue };
+
+                let lit = self.channel.spread(level, direction, &block);
+                if lit > self.channel.level(&block) {
+                    self.set_level(neighbour, lit);
+                    spread.push_back(neighbour);
+                }
+            }
+        }
+    }
+}
+
+/// Fixes up the light of the changed cells and everything lit through them, one channel after the other
+fn relight(world: &mut World, changed: &[(i32, i32)], touched: &mut HashSet<(i32, i32)>) {
+    for channel in Channel::ALL {
+        Relight { world, channel, touched }.run(changed);
+    }
+}
+
+/// Relights a whole chunk like its blocks all just changed, along with whatever it lit or darkens around it.
+/// The top of the chunk below is relit as well, it might have taken open sky as long as this chunk wasn't loaded
+pub(super) fn relight_chunk(world: &mut World, (chunk_x, chunk_y): (i32, i32), touched: &mut HashSet<(i32, i32)>) {
+    let (left, bottom) = (chunk_x * CHUNK_WIDTH as i32, chunk_y * CHUNK_HEIGHT as i32);
+
+    let mut changed: Vec<(i32, i32)> = (bottom..bottom + CHUNK_HEIGHT as i32)
+        .flat_map(|y| (left..left + CHUNK_WIDTH as i32).map(move |x| (x, y)))
+        .collect();
+    changed.extend((left..left + CHUNK_WIDTH as i32).map(|x| (x, bottom - 1)));
+    touched.insert((chunk_x, chunk_y));
+
+    relight(world, &changed, touched);
+}
+
+/// Relights the cells around a chunk that was just unloaded, since the light it gave them is gone.
+/// The top of the chunk below goes back to open sky, if its terrain has any
+pub fn relight_around(world: &mut World, (chunk_x, chunk_y): (i32, i32), touched: &mut HashSet<(i32, i32)>) {
+    let (left, bottom) = (chunk_x * CHUNK_WIDTH as i32, chunk_y * CHUNK_HEIGHT as i32);
+    let (right, top) = (left + CHUNK_WIDTH as i32, bottom + CHUNK_HEIGHT as i32);
+
+    // cells in chunks that aren't loaded are skipped
+    let changed: Vec<(i32, i32)> = (left..right).flat_map(|x| [(x, bottom - 1), (x, top)])
+        .chain((bottom..top).flat_map(|y| [(left - 1, y), (right, y)]))
+        .collect();
+
+    relight(world, &changed, touched);
+}
+
+pub fn update_block_light(
+    mut ev_update_light: EventReader<UpdateBlockLight>,
+    mut ev_draw_chunk: EventWriter<DrawChunk>,
+    mut world: ResMut<World>,
+) {
+    let mut changed: Vec<(i32, i32)> = ev_update_light.read().map(|ev| ev.position).collect();
+    if changed.is_empty() { return; }
+    changed.sort();
+    changed.dedup();
+
+    // the changed blocks are redrawn even if their light stays the same
+    let mut touched: HashSet<(i32, i32)> = changed.iter()
+        .map(|&position| World::locate_block(position).0)
+        .collect();
+
+    relight(&mut world, &changed, &mut touched);
+
+    for position in touched {
+        if let Some(chunk) = world.get_chunk(position) {
+            ev_draw_chunk.send(DrawChunk { chunk: *chunk });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::{block::Light, Chunk};
+
+    const OPAQUE: Block = Block { id: 3, is_solid: true, light_opacity: MAX_LIGHT_LEVEL, ..Block::AIR };
+    const LEAVES: Block = Block { id: 5, light_opacity: 2, ..Block::AIR };
+    const TORCH: Block = Block { id: 8, light_emission: Light::rgb(15, 12, 8), ..Block::AIR };
+
+    /// Empty chunks under open sky, with `blocks` placed in them, lit one after the other in `order`
+    fn lit_world(order: &[(i32, i32)], blocks: &[((i32, i32), Block)]) -> World {
+        let mut world = World::default();
+        for &position in order {
+            world.chunks.insert(position, Chunk { open_sky: [true; CHUNK_WIDTH], ..Chunk::new(position) });
+        }
+        for &(position, block) in blocks {
+            world.set_block_at(position, block);
+        }
+
+        let mut touched = HashSet::new();
+        for &position in order {
+            relight_chunk(&mut world, position, &mut touched);
+        }
+        world
+    }
+
+    /// Changes a block like `update_block_light` does, returning the chunks whose light was touched
+    fn set_block(world: &mut World, position: (i32, i32), block: Block) -> HashSet<(i32, i32)> {
+        world.set_block_at(position, block);
+        let mut touched = HashSet::new();
+        relight(world, &[position], &mut touched);
+        touched
+    }
+
+    fn light(world: &World, position: (i32, i32)) -> (u8, Light) {
+        let block = world.get_block_at(position).unwrap();
+        (block.sky_light, block.block_light)
+    }
+
+    #[test]
+    fn overhang_across_vertical_border() {
+        // the roof reaches from x 20 in the left chunk to x 33 in the right one
+        let roof: Vec<_> = (20..=33).map(|x| ((x, 20), OPAQUE)).collect();
+
+        for order in [[(0, 0), (1, 0)], [(1, 0), (0, 0)]] {
+            let world = lit_world(&order, &roof);
+
+            assert_eq!(light(&world, (31, 21)), (15, Light::NONE));
+            assert_eq!(light(&world, (31, 20)), (0, Light::NONE));
+            // sun light comes in from the open column at x 34 and crosses into the left chunk under the roof
+            assert_eq!(light(&world, (34, 19)), (15, Light::NONE));
+            assert_eq!(light(&world, (33, 19)), (12, Light::NONE));
+            assert_eq!(light(&world, (32, 19)), (9, Light::NONE));
+            assert_eq!(light(&world, (31, 19)), (6, Light::NONE));
+            assert_eq!(light(&world, (30, 19)), (3, Light::NONE));
+            assert_eq!(light(&world, (29, 19)), (0, Light::NONE));
+            assert_eq!(light(&world, (31, 0)), (6, Light::NONE));
+        }
+    }
+
+    #[test]
+    fn leaves_take_their_opacity_off_sun_light() {
+        let mut blocks: Vec<_> = (10..=14).map(|x| ((x, 25), LEAVES)).collect();
+        blocks.push(((12, 26), LEAVES));
+        let world = lit_world(&[(0, 0)], &blocks);
+
+        assert_eq!(light(&world, (9, 24)), (15, Light::NONE));
+        assert_eq!(light(&world, (11, 25)), (13, Light::NONE));
+        assert_eq!(light(&world, (11, 24)), (13, Light::NONE));
+        // two layers of leaves
+        assert_eq!(light(&world, (12, 26)), (13, Light::NONE));
+        assert_eq!(light(&world, (12, 25)), (11, Light::NONE));
+        assert_eq!(light(&world, (12, 24)), (11, Light::NONE));
+        assert_eq!(light(&world, (12, 0)), (11, Light::NONE));
+    }
+
+    #[test]
+    fn placing_a_torch_lights_across_border() {
+        let mut world = lit_world(&[(0, 0), (1, 0), (2, 0)], &[]);
+
+        let touched = set_block(&mut world, (30, 10), TORCH);
+
+        // the light runs out before x 64, so the third chunk stays as it was
+        assert_eq!(touched, HashSet::from([(0, 0), (1, 0)]));
+        assert_eq!(light(&world, (30, 10)), (15, Light::rgb(15, 12, 8)));
+        assert_eq!(light(&world, (29, 10)), (15, Light::rgb(14, 11, 7)));
+        assert_eq!(light(&world, (33, 10)), (15, Light::rgb(12, 9, 5)));
+        assert_eq!(light(&world, (38, 10)), (15, Light::rgb(7, 4, 0)));
+        assert_eq!(light(&world, (45, 10)), (15, Light::NONE));
+    }
+
+    #[test]
+    fn removing_a_torch_darkens_across_border() {
+        let mut world = lit_world(&[(0, 0), (1, 0)], &[((30, 10), TORCH)]);
+
+        assert_eq!(light(&world, (30, 10)), (15, Light::rgb(15, 12, 8)));
+        assert_eq!(light(&world, (31, 10)), (15, Light::rgb(14, 11, 7)));
+        assert_eq!(light(&world, (33, 10)), (15, Light::rgb(12, 9, 5)));
+        assert_eq!(light(&world, (38, 10)), (15, Light::rgb(7, 4, 0)));
+
+        let touched = set_block(&mut world, (30, 10), Block::AIR);
+
+        assert_eq!(touched, HashSet::from([(0, 0), (1, 0)]));
+        assert_eq!(light(&world, (30, 10)), (15, Light::NONE));
+        assert_eq!(light(&world, (33, 10)), (15, Light::NONE));
+        assert_eq!(light(&world, (38, 10)), (15, Light::NONE));
+    }
+
+    #[test]
+    fn closing_a_roof_darkens_across_border() {
+        let roof: Vec<_> = (20..=31).map(|x| ((x, 20), OPAQUE)).collect();
+        let mut world = lit_world(&[(0, 0), (1, 0)], &roof);
+
+        assert_eq!(light(&world, (32, 19)), (15, Light::NONE));
+        assert_eq!(light(&world, (31, 19)), (12, Light::NONE));
+        assert_eq!(light(&world, (30, 19)), (9, Light::NONE));
+
+        let touched = set_block(&mut world, (32, 20), OPAQUE);
+
+        assert!(touched.contains(&(0, 0)));
+        assert_eq!(light(&world, (32, 20)), (0, Light::NONE));
+        assert_eq!(light(&world, (32, 19)), (12, Light::NONE));
+        assert_eq!(light(&world, (31, 19)), (9, Light::NONE));
+        assert_eq!(light(&world, (30, 19)), (6, Light::NONE));
+        assert_eq!(light(&world, (30, 0)), (6, Light::NONE));
+    }
+
+    #[test]
+    fn sky_only_comes_from_open_terrain() {
+        let mut world = World::default();
+        world.chunks.insert((0, 0), Chunk::new((0, 0)));
+        world.chunks.insert((0, 1), Chunk { open_sky: [true; CHUNK_WIDTH], ..Chunk::new((0, 1)) });
+        let mut touched = HashSet::new();
+        relight_chunk(&mut world, (0, 0), &mut touched);
+
+        // underground, the top of the loaded area isn't open sky
+        assert_eq!(light(&world, (5, 31)), (0, Light::NONE));
+
+        relight_chunk(&mut world, (0, 1), &mut touched);
+        assert_eq!(light(&world, (5, 31)), (15, Light::NONE));
+        assert_eq!(light(&world, (5, 0)), (15, Light::NONE));
+
+        // unloading the chunk above takes its sun light along
+        world.chunks.remove(&(0, 1));
+        relight_around(&mut world, (0, 1), &mut touched);
+        assert_eq!(light(&world, (5, 31)), (0, Light::NONE));
+        assert_eq!(light(&world, (5, 0)), (0, Light::NONE));
+    }
+}
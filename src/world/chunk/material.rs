@@ -0,0 +1,94 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{MeshVertexAttribute, MeshVertexBufferLayoutRef},
+        render_resource::{AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError, VertexFormat}
+    },
+    sprite::{AlphaMode2d, Material2d, Material2dKey, Material2dPlugin}
+};
+
+use crate::world::clock::WorldClock;
+
+use super::atlas::BlockAtlas;
+
+const SHADER_PATH: &str = "shaders/chunk.wgsl";
+
+/// Sky light of a vertex from 0 to 1, before the time of day is applied. Block light goes in the vertex color
+pub const ATTRIBUTE_SKY_LIGHT: MeshVertexAttribute = MeshVertexAttribute::new("SkyLight", 0x5c1e_7a11_0b1d_9e01, VertexFormat::Float32);
+
+pub struct ChunkMaterialPlugin;
+
+impl Plugin for ChunkMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<ChunkMaterial>::default());
+        app.add_systems(Startup, init_chunk_material);
+        app.add_systems(Update, update_sky_light);
+    }
+}
+
+/// Block atlas lit by the block and sky light of every vertex. The color of the sky light is a uniform,
+/// so day turning into night doesn't touch a single mesh
+#[derive(Asset, TypePath, AsBindGroup, Clone)]
+pub struct ChunkMaterial {
+    #[uniform(0)]
+    sky_light: LinearRgba,
+    #[texture(1)]
+    #[sampler(2)]
+    atlas: Handle<Image>,
+}
+
+impl Material2d for ChunkMaterial {
+    fn vertex_shader() -> ShaderRef {
+        SHADER_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        SHADER_PATH.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        AlphaMode2d::Blend
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayoutRef,
+        _key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.0.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(4),
+            ATTRIBUTE_SKY_LIGHT.at_shader_location(5),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+/// The one material every chunk mesh is drawn with
+#[derive(Resource)]
+pub struct ChunkMaterialHandle(pub Handle<ChunkMaterial>);
+
+fn init_chunk_material(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    atlas: Res<BlockAtlas>,
+) {
+    // the sky light is set from the clock every frame
+    let material = materials.add(ChunkMaterial {
+        sky_light: LinearRgba::WHITE,
+        atlas: atlas.image.clone(),
+    });
+    commands.insert_resource(ChunkMaterialHandle(material));
+}
+
+fn update_sky_light(
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    material: Res<ChunkMaterialHandle>,
+    clock: Res<WorldClock>,
+) {
+    if let Some(material) = materials.get_mut(&material.0) {
+        material.sky_light = clock.sky_light();
+    }
+}
@@ -0,0 +1,84 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::{save::WorldSave, world::chunk::block::Block};
+
+/// Real seconds a whole day and night take
+const DAY_SECONDS: f32 = 720.;
+
+/// Time of day a new world starts at, a while after sunrise
+pub const START_TIME: f32 = 0.3;
+
+/// Sky light at midnight, a dim blue so the surface isn't pitch black
+const NIGHT_LIGHT: [f32; 3] = [0.12, 0.14, 0.24];
+
+/// Sunrise and sunset tint the sky light by this at their peak
+const TWILIGHT_TINT: [f32; 3] = [1.0, 0.75, 0.55];
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldClock>();
+        app.add_systems(Startup, restore_clock);
+        app.add_systems(Update, advance_clock);
+    }
+}
+
+#[derive(Resource)]
+pub struct WorldClock {
+    /// Time of day from 0 to 1. 0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset
+    pub time: f32,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        Self { time: START_TIME }
+    }
+}
+
+impl WorldClock {
+    /// How much of the sun's light there is, 1 during the day and 0 at night, with a short twilight around sunrise and sunset
+    pub fn sun(&self) -> f32 {
+        let height = -(self.time * TAU).cos();
+        (height * 4. + 0.5).clamp(0., 1.)
+    }
+
+    /// How far into sunrise or sunset it is, 1 halfway through and 0 outside of them
+    pub fn twilight(&self) -> f32 {
+        1. - (self.sun() * 2. - 1.).abs()
+    }
+
+    /// Color of full sky light right now
+    pub fn sky_light(&self) -> LinearRgba {
+        let (sun, twilight) = (self.sun(), self.twilight());
+        let channel = |i: usize| {
+            let light = NIGHT_LIGHT[i] + (1. - NIGHT_LIGHT[i]) * sun;
+            light * (1. + (TWILIGHT_TINT[i] - 1.) * twilight)
+        };
+
+        LinearRgba::rgb(channel(0), channel(1), channel(2))
+    }
+
+    /// Light level of `block` right now, its sky light dimmed like the sky is
+    pub fn light_level(&self, block: &Block) -> u8 {
+        let sky = self.sky_light();
+        let sky_light = (block.sky_light as f32 * sky.red.max(sky.green).max(sky.blue)).round() as u8;
+        sky_light.max(block.block_light.level())
+    }
+}
+
+fn restore_clock(
+    mut clock: ResMut<WorldClock>,
+    save: Res<WorldSave>,
+) {
+    clock.time = save.time_of_day;
+}
+
+fn advance_clock(
+    mut clock: ResMut<WorldClock>,
+    time: Res<Time>,
+) {
+    clock.time = (clock.time + time.delta_secs() / DAY_SECONDS).fract();
+}
@@ -0,0 +1,236 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{inventory::item::ItemDatabase, item_pickup::SpawnItemPickup, BLOCK_SIZE_PX};
+
+use super::{
+    chunk::{atlas::BlockAtlas, block::{Block, BlockLayer}, light::{update_block_light, UpdateBlockLight}},
+    set_block_at_position,
+    SetBlock,
+    World
+};
+
+/// A falling block counts as landed once it's slower than this, in pixels per second
+const LANDING_SPEED: f32 = 1.0;
+
+pub struct FallingBlockPlugin;
+
+impl Plugin for FallingBlockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (
+            drop_unsupported_blocks.after(set_block_at_position).before(update_block_light),
+            land_falling_blocks,
+        ));
+    }
+}
+
+#[derive(Component)]
+#[require(RigidBody(dynamic_rb), LockedAxes(locked_axes), Velocity, Ccd(ccd_enabled))]
+pub struct FallingBlock {
+    block: Block,
+}
+
+fn dynamic_rb() -> RigidBody {
+    RigidBody::Dynamic
+}
+
+fn locked_axes() -> LockedAxes {
+    LockedAxes::ROTATION_LOCKED | LockedAxes::TRANSLATION_LOCKED_X
+}
+
+fn ccd_enabled() -> Ccd {
+    Ccd::enabled()
+}
+
+/// Blocks a falling block replaces when it lands in their cell
+fn is_crushable(block: Block) -> bool {
+    !block.is_solid
+}
+
+/// Gravity block at a position that has nothing solid under it
+fn unsupported(world: &World, (x, y): (i32, i32)) -> Option<Block> {
+    let block = world.get_block_at((x, y)).filter(|block| block.has_gravity)?;
+    let below = world.get_block_at((x, y-1))?;
+    (!below.is_solid).then_some(block)
+}
+
+/// Takes the gravity blocks that lost their support out of the grid, with the cells they were in.
+/// The changed block itself falls if it was placed in the air, and whatever is stacked on it falls when it was broken
+fn take_unsupported(world: &mut World, (x, changed_y): (i32, i32)) -> Vec<((i32, i32), Block)> {
+    let mut taken = vec![];
+
+    for y in changed_y.. {
+        let Some(block) = unsupported(world, (x, y)) else {
+            // a broken block isn't falling itself, but the column above it still has to be checked
+            if y == changed_y { continue; }
+            break;
+        };
+
+        if world.set_block_at((x, y), Block::AIR).is_none() { break; }
+        taken.push(((x, y), block));
+    }
+
+    taken
+}
+
+fn drop_unsupported_blocks(
+    mut commands: Commands,
+    mut ev_set_block: EventReader<SetBlock>,
+    mut ev_update_light: EventWriter<UpdateBlockLight>,
+    mut world: ResMut<World>,
+    atlas: Res<BlockAtlas>,
+) {
+    for ev in ev_set_block.read() {
+        if let BlockLayer::Background = ev.layer { continue; }
+
+        let block = (ev.position / BLOCK_SIZE_PX).floor();
+
+        // gone from the grid right away, so the falling block doesn't start inside the chunk collider. The edit still
+        // goes through `SetBlock` like any other, which finds air and drops nothing but wakes liquids and ticks
+        for ((x, y), block) in take_unsupported(&mut world, (block.x as i32, block.y as i32)) {
+            ev_update_light.send(UpdateBlockLight { position: (x, y) });
+            commands.send_event(SetBlock {
+                block: Block::AIR,
+                position: Vec2::new(x as f32, y as f32) * BLOCK_SIZE_PX,
+                layer: BlockLayer::Foreground,
+                can_overwrite: true,
+                tool: None,
+            });
+
+            commands.spawn((
+                FallingBlock { block },
+                Sprite {
+                    image: atlas.image.clone(),
+                    rect: Some(atlas.tile_rect(block.tile)),
+                    ..default()
+                },
+                Transform::from_translation(Vec3::new(
+                    (x as f32 + 0.5) * BLOCK_SIZE_PX, (y as f32 + 0.5) * BLOCK_SIZE_PX, 1.0
+                )),
+                // a bit narrower than a block, so it doesn't scrape along the walls of a shaft
+                Collider::cuboid(BLOCK_SIZE_PX / 2. - 1., BLOCK_SIZE_PX / 2.),
+                CollisionGroups::new(Group::GROUP_4, Group::GROUP_2 | Group::GROUP_4),
+                Friction::coefficient(0.0),
+                Restitution::coefficient(0.0),
+            ));
+        }
+    }
+}
+
+fn land_falling_blocks(
+    mut commands: Commands,
+    mut ev_set_block: EventWriter<SetBlock>,
+    mut ev_spawn_item_pickup: EventWriter<SpawnItemPickup>,
+    world: Res<World>,
+    item_database: Res<ItemDatabase>,
+    q_falling_blocks: Query<(Entity, &FallingBlock, &Transform, &Velocity)>,
+) {
+    for (entity, falling_block, transform, velocity) in q_falling_blocks.iter() {
+        let cell = (transform.translation.truncate() / BLOCK_SIZE_PX).floor();
+        let (x, mut y) = (cell.x as i32, cell.y as i32);
+
+        // there is no ground to land on outside the loaded world and the block couldn't be saved there, so it drops as an item
+        let Some(below) = world.get_block_at((x, y-1)).filter(|_| world.get_block_at((x, y)).is_some()) else {
+            if let Some(id) = falling_block.block.drop_item {
+                ev_spawn_item_pickup.send(SpawnItemPickup {
+                    item: item_database.get_by_id(id),
+                    amount: 1,
+                    position: transform.translation.truncate(),
+                });
+            }
+            commands.entity(entity).despawn();
+            continue;
+        };
+
+        if velocity.linvel.y.abs() > LANDING_SPEED || !below.is_solid { continue; }
+
+        // the chunk collider lags behind the grid, so a block landing on a freshly landed one can end up inside it
+        while world.get_block_at((x, y)).is_some_and(|block| !is_crushable(block)) {
+            y += 1;
+        }
+
+        let position = Vec2::new(x as f32, y as f32) * BLOCK_SIZE_PX;
+
+        // whatever was in the cell drops as an item
+        if world.get_block_at((x, y)).is_some_and(|block| block.id != 0) {
+            ev_set_block.send(SetBlock {
+                block: Block::AIR,
+                position,
+                layer: BlockLayer::Foreground,
+                can_overwrite: true,
+                tool: None,
+            });
+        }
+
+        ev_set_block.send(SetBlock {
+            block: falling_block.block,
+            position,
+            layer: BlockLayer::Foreground,
+            can_overwrite: true,
+            tool: None,
+        });
+
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::Chunk;
+
+    const STONE: Block = Block { id: 1, is_solid: true, ..Block::AIR };
+    const DIRT: Block = Block { id: 2, is_solid: true, ..Block::AIR };
+    const SAND: Block = Block { id: 3, is_solid: true, has_gravity: true, ..Block::AIR };
+
+    /// A column of sand on dirt on the stone floor of an empty chunk
+    fn sand_on_dirt() -> World {
+        let mut world = World::default();
+        world.chunks.insert((0, 0), Chunk::new((0, 0)));
+        world.set_block_at((5, 0), STONE);
+        world.set_block_at((5, 1), DIRT);
+        for y in 2..=5 {
+            world.set_block_at((5, y), SAND);
+        }
+        world
+    }
+
+    #[test]
+    fn breaking_the_support_drops_the_whole_column() {
+        let mut world = sand_on_dirt();
+
+        world.set_block_at((5, 1), Block::AIR);
+        let taken = take_unsupported(&mut world, (5, 1));
+
+        let cells: Vec<(i32, i32)> = taken.iter().map(|(cell, _)| *cell).collect();
+        assert_eq!(cells, [(5, 2), (5, 3), (5, 4), (5, 5)]);
+        assert!(taken.iter().all(|(_, block)| block.id == SAND.id));
+        assert!((1..=5).all(|y| world.get_block_at((5, y)).unwrap().id == 0));
+    }
+
+    #[test]
+    fn gravity_block_placed_in_the_air_falls() {
+        let mut world = sand_on_dirt();
+
+        world.set_block_at((9, 10), SAND);
+        let taken = take_unsupported(&mut world, (9, 10));
+
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].0, (9, 10));
+        assert_eq!(world.get_block_at((9, 10)).unwrap().id, 0);
+    }
+
+    #[test]
+    fn supported_blocks_stay() {
+        let mut world = sand_on_dirt();
+
+        // a block placed on top of the column is held up by it
+        world.set_block_at((5, 6), SAND);
+        assert!(take_unsupported(&mut world, (5, 6)).is_empty());
+        // breaking a block beside the column takes nothing with it
+        world.set_block_at((6, 1), DIRT);
+        world.set_block_at((6, 1), Block::AIR);
+        assert!(take_unsupported(&mut world, (6, 1)).is_empty());
+        assert!((2..=6).all(|y| world.get_block_at((5, y)).unwrap().id == SAND.id));
+    }
+}
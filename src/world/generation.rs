@@ -0,0 +1,322 @@
+use std::{collections::HashMap, fs::{self, File}, io::Read, path::Path};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+
+use crate::{pack::ContentPacks, registry::read_entries, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+use super::chunk::{
+    block::{Block, BlockDatabase},
+    block_structure::{BlockStructure, StructureTemplate},
+    Chunk
+};
+
+pub mod biomes;
+use biomes::{Biome, BiomesPass, ColumnBiome};
+
+mod terrain;
+use terrain::TerrainPass;
+
+mod caves;
+use caves::CavesPass;
+
+mod surface;
+use surface::SurfacePass;
+
+mod liquids;
+use liquids::LiquidsPass;
+
+mod ores;
+use ores::OresPass;
+
+mod structures;
+use structures::StructuresPass;
+
+mod decorations;
+use decorations::DecorationsPass;
+
+pub mod trees;
+use trees::TreeSpecies;
+
+const PRESET_FILE: &str = "world_gen_preset.json";
+const BIOMES_FILE: &str = "biome_data.json";
+const STRUCTURES_DIR: &str = "structures";
+const TREE_SPECIES_FILE: &str = "tree_species.json";
+
+pub struct WorldGenPlugin;
+
+impl Plugin for WorldGenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, init_pipeline);
+    }
+}
+
+/// Chunk position of the bottom left corner and the structure placed there
+pub type PlacedStructure = ((usize, usize), BlockStructure);
+
+/// One step of chunk generation. Passes run in the order of the preset and share a `GenContext`
+pub trait WorldGenPass: Send + Sync {
+    fn apply(&self, chunk: &mut Chunk, ctx: &mut GenContext);
+}
+
+/// State handed from one pass to the next while a single chunk is generated
+pub struct GenContext<'a> {
+    pub position: (i32, i32),
+    pub seed: u32,
+    pub block_database: &'a BlockDatabase,
+    pub biomes: &'a [Biome],
+    pub templates: &'a HashMap<String, StructureTemplate>,
+    pub tree_species: &'a HashMap<String, TreeSpecies>,
+    /// Filled by the biomes pass
+    pub columns: [ColumnBiome; CHUNK_WIDTH],
+    /// Surface height of every column, in world blocks. Filled by the terrain pass
+    pub heights: [i32; CHUNK_WIDTH],
+    /// Filled by the caves pass, read by the surface pass
+    pub cave_density: Vec<[f64; CHUNK_HEIGHT]>,
+    /// Chunk positions of the top block of every column that got a surface block
+    pub surface: Vec<(usize, usize)>,
+    pub structures: Vec<PlacedStructure>,
+}
+
+impl<'a> GenContext<'a> {
+    fn new(position: (i32, i32), seed: u32, block_database: &'a BlockDatabase, pipeline: &'a WorldGenPipeline) -> Self {
+        Self {
+            position,
+            seed,
+            block_database,
+            biomes: &pipeline.biomes,
+            templates: &pipeline.templates,
+            tree_species: &pipeline.tree_species,
+            columns: [ColumnBiome::default(); CHUNK_WIDTH],
+            heights: [0; CHUNK_WIDTH],
+            cave_density: vec![[0.0; CHUNK_HEIGHT]; CHUNK_WIDTH],
+            surface: vec![],
+            structures: vec![],
+        }
+    }
+
+    pub fn world_x(&self, x: usize) -> f64 {
+        x as f64 + self.position.0 as f64 * CHUNK_WIDTH as f64
+    }
+
+    pub fn world_y(&self, y: usize) -> f64 {
+        y as f64 + self.position.1 as f64 * CHUNK_HEIGHT as f64
+    }
+
+    /// Rng unique to this chunk and `salt`, so passes don't shift each other's randomness
+    pub fn rng(&self, salt: u64) -> StdRng {
+        let chunk_seed = ((self.seed as u64) << 32) | self.position.0 as u32 as u64;
+        let row = (self.position.1 as u32 as u64).wrapping_mul(0xbf58476d1ce4e5b9);
+        StdRng::seed_from_u64(chunk_seed ^ row ^ salt.wrapping_mul(0x9e3779b97f4a7c15))
+    }
+
+    /// World height of the bottom row of the chunk
+    pub fn bottom(&self) -> i32 {
+        self.position.1 * CHUNK_HEIGHT as i32
+    }
+
+    /// Dominant biome of a column
+    pub fn biome(&self, x: usize) -> &'a Biome {
+        &self.biomes[self.columns[x].biome]
+    }
+
+    pub fn block(&self, name: &str) -> Block {
+        self.block_database.get_by_name(name).unwrap_or_else(|| {
+            error!("unknown block \"{name}\" in world generation data");
+            Block::AIR
+        })
+    }
+}
+
+/// Sum of noise samples, one per octave, each at `spread * octave` frequency
+pub fn octaves(octaves: &[f64], spread: f64, sample: impl Fn(f64) -> f64) -> f64 {
+    octaves.iter().map(|octave| sample(spread * octave)).sum()
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PassPreset {
+    Biomes(BiomesPass),
+    Terrain(TerrainPass),
+    Caves(CavesPass),
+    Surface(SurfacePass),
+    Liquids(LiquidsPass),
+    Ores(OresPass),
+    Structures(StructuresPass),
+    Decorations(DecorationsPass),
+}
+
+impl PassPreset {
+    fn into_pass(self) -> Box<dyn WorldGenPass> {
+        match self {
+            Self::Biomes(pass) => Box::new(pass),
+            Self::Terrain(pass) => Box::new(pass),
+            Self::Caves(pass) => Box::new(pass),
+            Self::Surface(pass) => Box::new(pass),
+            Self::Liquids(pass) => Box::new(pass),
+            Self::Ores(pass) => Box::new(pass),
+            Self::Structures(pass) => Box::new(pass),
+            Self::Decorations(pass) => Box::new(pass),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct GenerationPreset {
+    passes: Vec<PassPreset>,
+}
+
+impl GenerationPreset {
+    /// Settings the passes can't run with
+    fn validate(self) -> Result<Self, String> {
+        for (index, pass) in self.passes.iter().enumerate() {
+            if let PassPreset::Structures(StructuresPass { cell_width: 0, .. }) = pass {
+                return Err(format!("passes[{index}]: cell_width of the structures pass has to be at least 1"));
+            }
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Resource)]
+pub struct WorldGenPipeline {
+    passes: Vec<Box<dyn WorldGenPass>>,
+    biomes: Vec<Biome>,
+    /// Structure templates by file name, without the extension
+    templates: HashMap<String, StructureTemplate>,
+    tree_species: HashMap<String, TreeSpecies>,
+}
+
+impl WorldGenPipeline {
+    /// Runs every pass on a fresh chunk. Structures are returned to be placed by the caller,
+    /// since they can reach into neighbouring chunks
+    pub fn generate(&self, position: (i32, i32), seed: u32, block_database: &BlockDatabase) -> (Chunk, Vec<PlacedStructure>) {
+        let mut chunk = Chunk::new(position);
+        let mut ctx = GenContext::new(position, seed, block_database, self);
+
+        for pass in self.passes.iter() {
+            pass.apply(&mut chunk, &mut ctx);
+        }
+
+        let top = ctx.bottom() + CHUNK_HEIGHT as i32;
+        chunk.open_sky = ctx.heights.map(|height| height <= top);
+
+        (chunk, ctx.structures)
+    }
+
+    pub fn tree_species(&self, name: &str) -> Option<&TreeSpecies> {
+        self.tree_species.get(name)
+    }
+}
+
+fn read_to_string(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|err| format!("can't be read: {err}"))?;
+
+    let mut string = String::new();
+    file.read_to_string(&mut string).map_err(|err| format!("can't be read: {err}"))?;
+    Ok(string)
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    serde_json::from_str(&read_to_string(path)?).map_err(|err| format!("isn't valid: {err}"))
+}
+
+/// Every pack can add generation data, a later one replacing biomes, structures and tree species of the same name.
+/// The passes come from the last pack with a preset that can be read. Broken entries are logged and skipped,
+/// there is no pipeline if that leaves no preset or no biomes
+pub(super) fn load_pipeline(packs: &ContentPacks) -> Option<WorldGenPipeline> {
+    let presets: Vec<_> = packs.files(PRESET_FILE).map(|(_, path)| path).collect();
+    let preset = presets.iter().rev().find_map(|path| {
+        read_json::<GenerationPreset>(path)
+            .and_then(GenerationPreset::validate)
+            .inspect_err(|err| error!("{}: {err}, falling back to the preset of an earlier pack", path.display()))
+            .ok()
+    });
+
+    let mut biomes: Vec<Biome> = vec![];
+    for (_, path) in packs.files(BIOMES_FILE) {
+        let (entries, errors) = read_entries::<Biome>(&path, "biomes", &|path| fs::read_to_string(path).map_err(|err| err.to_string()));
+        for err in errors {
+            error!("{err}, skipping it");
+        }
+
+        for (_, biome) in entries {
+            match biomes.iter().position(|other| other.name == biome.name) {
+                Some(index) => biomes[index] = biome,
+                None => biomes.push(biome),
+            }
+        }
+    }
+
+    let mut templates = HashMap::new();
+    for (_, dir) in packs.files(STRUCTURES_DIR) {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                error!("{}: can't be read: {err}, skipping its structures", dir.display());
+                continue;
+            },
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") { continue; }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else { continue };
+
+            match read_json::<StructureTemplate>(&path).and_then(StructureTemplate::validate) {
+                Ok(template) => { templates.insert(name.to_string(), template); },
+                Err(err) => error!("{}: {err}, skipping it", path.display()),
+            }
+        }
+    }
+
+    let mut tree_species = HashMap::new();
+    for (_, path) in packs.files(TREE_SPECIES_FILE) {
+        let map = match read_json::<Map<String, Value>>(&path) {
+            Ok(map) => map,
+            Err(err) => {
+                error!("{}: {err}, skipping it", path.display());
+                continue;
+            },
+        };
+        let Some(species) = map.get("species").and_then(|species| species.as_object()) else {
+            error!("{}: has no \"species\" object, skipping it", path.display());
+            continue;
+        };
+
+        for (name, value) in species.iter() {
+            match TreeSpecies::deserialize(value).map_err(|err| err.to_string()).and_then(TreeSpecies::validate) {
+                Ok(entry) => { tree_species.insert(name.clone(), entry); },
+                Err(err) => error!("{}: species \"{name}\": {err}, skipping it", path.display()),
+            }
+        }
+    }
+
+    let Some(preset) = preset else {
+        error!("no pack has a {PRESET_FILE} that can be read");
+        return None;
+    };
+    if biomes.is_empty() {
+        error!("no pack has a biome in {BIOMES_FILE} that can be read");
+        return None;
+    }
+
+    let passes = preset.passes.into_iter().map(PassPreset::into_pass).collect();
+    Some(WorldGenPipeline { passes, biomes, templates, tree_species })
+}
+
+fn init_pipeline(
+    mut commands: Commands,
+    mut ev_exit: EventWriter<AppExit>,
+    packs: Res<ContentPacks>,
+) {
+    match load_pipeline(&packs) {
+        Some(pipeline) => commands.insert_resource(pipeline),
+        None => {
+            error!("world generation data has problems, exiting");
+            ev_exit.send(AppExit::error());
+        },
+    }
+}
@@ -0,0 +1,100 @@
+use noise::{NoiseFn, Perlin};
+use serde::Deserialize;
+
+use crate::{world::chunk::Chunk, CHUNK_WIDTH};
+
+use super::{GenContext, WorldGenPass};
+
+#[derive(Deserialize)]
+pub struct BiomeStructure {
+    /// Name of a tree species or of a template in `assets/structures/`
+    pub name: String,
+    /// Chance of a structure in each structure cell
+    pub chance: f64,
+}
+
+#[derive(Deserialize)]
+pub struct BiomeDecoration {
+    pub block: String,
+    pub chance: f64,
+}
+
+#[derive(Deserialize)]
+pub struct Biome {
+    pub name: String,
+    /// Where the biome sits on the biome noise, which stays roughly within -1..1
+    pub center: f64,
+    pub surface_block: String,
+    pub subsurface_block: String,
+    /// How many blocks under the surface are replaced with `subsurface_block`
+    pub subsurface_depth: usize,
+    /// Multiplies the terrain height noise
+    pub height_amplitude: f64,
+    /// Added to the cave thresholds, higher values mean more caves
+    pub cave_density: f64,
+    pub structures: Vec<BiomeStructure>,
+    pub decorations: Vec<BiomeDecoration>,
+}
+
+/// Biome of a single column, with the numeric parameters blended between neighbouring biomes
+#[derive(Clone, Copy)]
+pub struct ColumnBiome {
+    /// Index of the dominant biome, the one that gets to pick blocks and structures
+    pub biome: usize,
+    pub height_amplitude: f64,
+    pub cave_density: f64,
+}
+
+impl Default for ColumnBiome {
+    fn default() -> Self {
+        Self {
+            biome: 0,
+            height_amplitude: 1.0,
+            cave_density: 0.0,
+        }
+    }
+}
+
+/// Picks a biome for every column with a low frequency noise along x
+#[derive(Deserialize)]
+pub struct BiomesPass {
+    pub spread: f64,
+    /// Moves the biome noise away from the height noise, which uses the same seed
+    pub offset: f64,
+    /// Distance on the biome noise over which neighbouring biomes fade into each other
+    pub blend_width: f64,
+}
+
+impl WorldGenPass for BiomesPass {
+    fn apply(&self, _chunk: &mut Chunk, ctx: &mut GenContext) {
+        let perlin = Perlin::new(ctx.seed);
+
+        for x in 0..CHUNK_WIDTH {
+            let value = perlin.get([(ctx.world_x(x) + self.offset) * self.spread]);
+
+            let weights: Vec<f64> = ctx.biomes.iter()
+                .map(|biome| (-((value - biome.center) / self.blend_width).powi(2)).exp())
+                .collect();
+            let total: f64 = weights.iter().sum();
+
+            let Some((dominant, _)) = weights.iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            else { continue };
+
+            // far from every center all weights underflow to zero, the dominant biome wins outright
+            let blend = |param: fn(&Biome) -> f64| -> f64 {
+                if total <= f64::EPSILON {
+                    return param(&ctx.biomes[dominant]);
+                }
+                ctx.biomes.iter().zip(weights.iter()).map(|(biome, w)| param(biome) * w).sum::<f64>() / total
+            };
+
+            ctx.columns[x] = ColumnBiome {
+                biome: dominant,
+                height_amplitude: blend(|biome| biome.height_amplitude),
+                cave_density: blend(|biome| biome.cave_density),
+            };
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use noise::{NoiseFn, Simplex};
+use serde::Deserialize;
+
+use crate::{
+    world::chunk::{block::Block, Chunk},
+    CHUNK_HEIGHT, CHUNK_WIDTH
+};
+
+use super::{octaves, GenContext, WorldGenPass};
+
+/// Carves foreground blocks under the surface where the cave noise is low. Background stays intact
+#[derive(Deserialize)]
+pub struct CavesPass {
+    pub spread: f64,
+    pub octaves: Vec<f64>,
+    /// Part of the surface height under which `deep_threshold` is used instead of `shallow_threshold`
+    pub deep_ratio: f64,
+    pub shallow_threshold: f64,
+    pub deep_threshold: f64,
+}
+
+impl WorldGenPass for CavesPass {
+    fn apply(&self, chunk: &mut Chunk, ctx: &mut GenContext) {
+        let simplex = Simplex::new(ctx.seed);
+
+        for x in 0..CHUNK_WIDTH {
+            let xf = ctx.world_x(x);
+            let height = ctx.heights[x] as f64;
+
+            for y in 0..CHUNK_HEIGHT {
+                let yf = ctx.world_y(y);
+
+                let cave_density = octaves(&self.octaves, self.spread, |s| simplex.get([xf * s, yf * s]));
+                ctx.cave_density[x][y] = cave_density;
+
+                if yf >= height { continue; }
+
+                let density_check = if yf > height * self.deep_ratio {
+                    self.shallow_threshold
+                } else {
+                    self.deep_threshold
+                };
+
+                if cave_density <= density_check + ctx.columns[x].cave_density {
+                    chunk.data[x][y] = Block::AIR;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    world::chunk::{block_structure::BlockStructure, Chunk},
+    CHUNK_HEIGHT
+};
+
+use super::{GenContext, WorldGenPass};
+
+const RNG_SALT: u64 = 2;
+
+/// Scatters the non-solid decoration blocks of the surface biome on top of surface blocks
+#[derive(Deserialize)]
+pub struct DecorationsPass {}
+
+impl WorldGenPass for DecorationsPass {
+    fn apply(&self, chunk: &mut Chunk, ctx: &mut GenContext) {
+        let mut rng = ctx.rng(RNG_SALT);
+        let mut spilled = vec![];
+
+        for &(x, y) in ctx.surface.iter() {
+            let roll = rng.gen::<f64>();
+
+            let mut chance = 0.0;
+            let Some(decoration) = ctx.biome(x).decorations.iter().find(|decoration| {
+                chance += decoration.chance;
+                roll < chance
+            }) else { continue };
+
+            let block = ctx.block(&decoration.block);
+
+            // on the top row the decoration goes into the chunk above, the same way structures do
+            if y + 1 >= CHUNK_HEIGHT {
+                spilled.push(((x, y + 1), BlockStructure {
+                    data: vec![vec![block.id]],
+                    bg_data: vec![vec![0]],
+                    air_mask: vec![vec![false]],
+                    anchor: (0, 0),
+                }));
+                continue;
+            }
+
+            chunk.data[x][y+1] = block;
+        }
+
+        ctx.structures.extend(spilled);
+    }
+}
@@ -0,0 +1,72 @@
+use noise::{NoiseFn, Simplex};
+use serde::Deserialize;
+
+use crate::{world::chunk::Chunk, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+use super::{GenContext, WorldGenPass};
+
+#[derive(Deserialize)]
+pub struct CavePool {
+    pub block: String,
+    /// World heights the pools can be in, bottom inclusive and top exclusive
+    pub min_y: i32,
+    pub max_y: i32,
+    pub spread: f64,
+    /// Moves the pool noise away from the other noises, which use the same seed
+    pub offset: f64,
+    /// Cave air is filled where the pool noise is above this
+    pub threshold: f64,
+}
+
+/// Floods the surface up to the lake level wherever it dips under it, and fills patches of caves.
+/// Nothing here has to be level, the liquid simulation settles it once the chunk is loaded
+/// and only puts the chunk in the save when liquid leaves it
+#[derive(Deserialize)]
+pub struct LiquidsPass {
+    pub lake_block: String,
+    /// Columns with a lower surface are filled up to this world height, exclusive
+    pub lake_level: i32,
+    pub pools: Vec<CavePool>,
+}
+
+impl WorldGenPass for LiquidsPass {
+    fn apply(&self, chunk: &mut Chunk, ctx: &mut GenContext) {
+        let simplex = Simplex::new(ctx.seed);
+        let lake = ctx.block(&self.lake_block);
+
+        for x in 0..CHUNK_WIDTH {
+            let height = ctx.heights[x];
+
+            for y in 0..CHUNK_HEIGHT {
+                let world_y = ctx.bottom() + y as i32;
+                if world_y > height && world_y < self.lake_level && chunk.data[x][y].id == 0 {
+                    chunk.data[x][y] = lake;
+                }
+            }
+        }
+
+        // nothing grows on the bottom of a lake
+        let heights = ctx.heights;
+        ctx.surface.retain(|&(x, _)| heights[x] + 1 >= self.lake_level);
+
+        for pool in self.pools.iter() {
+            let block = ctx.block(&pool.block);
+
+            for x in 0..CHUNK_WIDTH {
+                let xf = ctx.world_x(x) + pool.offset;
+
+                for y in 0..CHUNK_HEIGHT {
+                    let world_y = ctx.bottom() + y as i32;
+                    if world_y < pool.min_y || world_y >= pool.max_y || world_y >= ctx.heights[x] { continue; }
+                    // only cave air, with a wall behind it
+                    if chunk.data[x][y].id != 0 || chunk.background_data[x][y].id == 0 { continue; }
+
+                    let yf = ctx.world_y(y) + pool.offset;
+                    if simplex.get([xf * pool.spread, yf * pool.spread]) > pool.threshold {
+                        chunk.data[x][y] = block;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{world::chunk::Chunk, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+use super::{GenContext, WorldGenPass};
+
+const RNG_SALT: u64 = 3;
+
+#[derive(Deserialize)]
+pub struct OreVein {
+    pub block: String,
+    /// Only these blocks are replaced, so veins never float in caves or stick out of the surface
+    pub host_blocks: Vec<String>,
+    /// World heights a vein can start at, bottom inclusive and top exclusive
+    pub min_y: i32,
+    pub max_y: i32,
+    /// Steps of the random walk that grows a vein
+    pub vein_size: u32,
+    /// Average number of veins per column of chunks
+    pub frequency: f64,
+}
+
+/// Grows veins of ore blocks inside their host block by random walks
+#[derive(Deserialize)]
+pub struct OresPass {
+    pub ores: Vec<OreVein>,
+}
+
+impl WorldGenPass for OresPass {
+    fn apply(&self, chunk: &mut Chunk, ctx: &mut GenContext) {
+        let mut rng = ctx.rng(RNG_SALT);
+        let chunk_bottom = ctx.bottom();
+        let chunk_top = chunk_bottom + CHUNK_HEIGHT as i32;
+
+        for ore in self.ores.iter() {
+            let block = ctx.block(&ore.block);
+            let hosts: Vec<u32> = ore.host_blocks.iter().map(|name| ctx.block(name).id).collect();
+
+            // every chunk of the column starts the veins of its own share of the height range
+            let (min_y, max_y) = (ore.min_y.max(chunk_bottom), ore.max_y.max(ore.min_y + 1).min(chunk_top));
+            if min_y >= max_y { continue; }
+
+            let frequency = ore.frequency * (max_y - min_y) as f64 / (ore.max_y - ore.min_y).max(1) as f64;
+            let mut veins = frequency.floor() as u32;
+            if rng.gen_bool(frequency.fract()) {
+                veins += 1;
+            }
+
+            for _ in 0..veins {
+                let mut x = rng.gen_range(0..CHUNK_WIDTH as i32);
+                let mut y = rng.gen_range(min_y..max_y) - chunk_bottom;
+
+                for _ in 0..ore.vein_size {
+                    if (0..CHUNK_WIDTH as i32).contains(&x) && (0..CHUNK_HEIGHT as i32).contains(&y) {
+                        let (cx, cy) = (x as usize, y as usize);
+                        if hosts.contains(&chunk.data[cx][cy].id) {
+                            chunk.data[cx][cy] = block;
+                        }
+                    }
+
+                    match rng.gen_range(0..4) {
+                        0 => x += 1,
+                        1 => x -= 1,
+                        2 => y += 1,
+                        _ => y -= 1,
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use bevy::log::warn;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{world::chunk::Chunk, CHUNK_WIDTH};
+
+use super::{GenContext, WorldGenPass};
+
+const RNG_SALT: u64 = 1;
+
+/// Places the structures of the surface biome, at most one per cell and jittered inside it
+#[derive(Deserialize)]
+pub struct StructuresPass {
+    /// At least 1, checked when the preset loads
+    pub cell_width: usize,
+    /// Keep it at or below `cell_width` minus the structure width, so neighbouring structures don't overlap
+    pub jitter: usize,
+}
+
+impl WorldGenPass for StructuresPass {
+    fn apply(&self, _chunk: &mut Chunk, ctx: &mut GenContext) {
+        let mut rng = ctx.rng(RNG_SALT);
+
+        for cell in 0..CHUNK_WIDTH / self.cell_width {
+            let x = cell * self.cell_width + rng.gen_range(0..self.jitter.max(1));
+            let roll = rng.gen::<f64>();
+
+            let Some(&(_, y)) = ctx.surface.iter().find(|(surface_x, _)| *surface_x == x) else { continue };
+
+            // chances stack, so a biome can mix rare and common structures in one cell
+            let mut chance = 0.0;
+            let Some(entry) = ctx.biome(x).structures.iter().find(|entry| {
+                chance += entry.chance;
+                roll < chance
+            }) else { continue };
+
+            let structure = if let Some(species) = ctx.tree_species.get(&entry.name) {
+                species.grow(&mut rng, ctx.block_database)
+            }
+            else if let Some(template) = ctx.templates.get(&entry.name) {
+                template.instantiate(&mut rng, ctx.block_database)
+            }
+            else {
+                warn!("unknown structure \"{}\" in biome \"{}\"", entry.name, ctx.biome(x).name);
+                continue;
+            };
+
+            let Some(structure) = structure else { continue };
+            ctx.structures.push(((x, y), structure));
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use serde::Deserialize;
+
+use crate::{world::chunk::Chunk, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+use super::{GenContext, WorldGenPass};
+
+/// Puts the biome's top block on the heightmap, unless a cave opens up right there,
+/// and swaps the soil right under it for the biome's subsurface block
+#[derive(Deserialize)]
+pub struct SurfacePass {
+    /// No surface block where the cave density is at or below this
+    pub cave_threshold: f64,
+}
+
+impl WorldGenPass for SurfacePass {
+    fn apply(&self, chunk: &mut Chunk, ctx: &mut GenContext) {
+        for x in 0..CHUNK_WIDTH {
+            let biome = ctx.biome(x);
+            let subsurface = ctx.block(&biome.subsurface_block);
+
+            // the subsurface layer can reach into the chunk below the surface block
+            let y = ctx.heights[x] - ctx.bottom();
+            let sub_bottom = (y - biome.subsurface_depth as i32).clamp(0, CHUNK_HEIGHT as i32) as usize;
+            let sub_top = y.clamp(0, CHUNK_HEIGHT as i32) as usize;
+
+            for sub_y in sub_bottom..sub_top {
+                if chunk.data[x][sub_y].id != 0 {
+                    chunk.data[x][sub_y] = subsurface;
+                }
+                chunk.background_data[x][sub_y] = subsurface;
+            }
+
+            if y < 0 || y >= CHUNK_HEIGHT as i32 { continue; }
+            let y = y as usize;
+
+            if ctx.cave_density[x][y] <= self.cave_threshold { continue; }
+
+            chunk.data[x][y] = ctx.block(&biome.surface_block);
+            chunk.background_data[x][y] = subsurface;
+            ctx.surface.push((x, y));
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use noise::{NoiseFn, Perlin, Simplex};
+use serde::Deserialize;
+
+use crate::{world::chunk::Chunk, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+use super::{octaves, GenContext, WorldGenPass};
+
+/// Fills everything under the heightmap, choosing between soil and rock by a density noise.
+/// The height noise is scaled by the biome of each column
+#[derive(Deserialize)]
+pub struct TerrainPass {
+    pub height_spread: f64,
+    pub height_octaves: Vec<f64>,
+    pub height_amplitude: f64,
+    pub base_height: f64,
+    pub density_spread: f64,
+    pub density_octaves: Vec<f64>,
+    /// Moves the density noise away from the caves noise, which uses the same seed
+    pub density_offset: f64,
+    /// Part of the surface height under which `deep_threshold` is used instead of `shallow_threshold`
+    pub deep_ratio: f64,
+    pub shallow_threshold: f64,
+    pub deep_threshold: f64,
+    pub soil_block: String,
+    pub rock_block: String,
+}
+
+impl WorldGenPass for TerrainPass {
+    fn apply(&self, chunk: &mut Chunk, ctx: &mut GenContext) {
+        let perlin = Perlin::new(ctx.seed);
+        let simplex = Simplex::new(ctx.seed);
+
+        let soil = ctx.block(&self.soil_block);
+        let rock = ctx.block(&self.rock_block);
+
+        for x in 0..CHUNK_WIDTH {
+            let xf = ctx.world_x(x);
+
+            let height = octaves(&self.height_octaves, self.height_spread, |s| perlin.get([xf * s]));
+            let height = (height * self.height_amplitude * ctx.columns[x].height_amplitude + self.base_height).floor();
+            ctx.heights[x] = height as i32;
+
+            for y in 0..CHUNK_HEIGHT {
+                let yf = ctx.world_y(y);
+                if yf >= height { break; }
+
+                let (xo, yo) = (xf + self.density_offset, yf + self.density_offset);
+                let block_density = octaves(&self.density_octaves, self.density_spread, |s| simplex.get([xo * s, yo * s]));
+
+                let density_check = if yf > height * self.deep_ratio {
+                    self.shallow_threshold
+                } else {
+                    self.deep_threshold
+                };
+
+                let block = if block_density > density_check { soil } else { rock };
+                chunk.data[x][y] = block;
+                chunk.background_data[x][y] = block;
+            }
+        }
+    }
+}
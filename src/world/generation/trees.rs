@@ -0,0 +1,316 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::log::warn;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::world::chunk::{block::BlockDatabase, block_structure::BlockStructure};
+
+/// The axiom grows past this many symbols only by mistake, stop rewriting there
+const MAX_SENTENCE_LENGTH: usize = 4096;
+
+/// Symbols the turtle draws something for
+const DRAWING_SYMBOLS: [char; 4] = ['F', 'f', 'L', 'l'];
+
+/// Turtle directions in 45 degree steps, clockwise from straight up
+const DIRECTIONS: [(i32, i32); 8] = [
+    (0, 1), (1, 1), (1, 0), (1, -1),
+    (0, -1), (-1, -1), (-1, 0), (-1, 1),
+];
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+pub struct Production {
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub to: String,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(tag = "shape", rename_all = "snake_case")]
+pub enum Canopy {
+    Round { radius: i32 },
+    /// Widens from a point above the turtle down to `radius`, like a pine
+    Cone { radius: i32, height: i32 },
+    /// Twice as wide as it is tall
+    Flat { radius: i32 },
+    /// Round, with every leaf kept by `density` chance
+    Blob { radius: i32, density: f64 },
+}
+
+/// Stochastic L-system drawn on the block grid by a turtle.
+///
+/// `F` draws a log in both layers, `f` draws a background log, `+` and `-` turn 45 degrees right and left,
+/// `|` turns around, `[` and `]` save and restore the turtle, `L` puts the canopy and `l` a smaller one.
+/// Other symbols are only there to be rewritten
+#[derive(Deserialize)]
+pub struct TreeSpecies {
+    pub log_block: String,
+    pub leaves_block: String,
+    pub axiom: String,
+    pub rules: HashMap<char, Vec<Production>>,
+    pub iterations: u32,
+    pub canopy: Canopy,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cell {
+    Log,
+    BackgroundLog,
+    Leaves,
+}
+
+impl TreeSpecies {
+    /// Rules a species can't grow with, like a sentence that can never draw anything or a canopy density that isn't a chance
+    pub fn validate(self) -> Result<Self, String> {
+        if let Canopy::Blob { density, .. } = self.canopy {
+            if !(0.0..=1.0).contains(&density) {
+                return Err(format!("canopy density {density} isn't between 0 and 1"));
+            }
+        }
+
+        // symbols that end up drawing something with the rewrites still left, none are left at first
+        let mut drawing: HashSet<char> = DRAWING_SYMBOLS.into_iter().collect();
+
+        for _ in 0..self.iterations {
+            let mut next: HashSet<char> = DRAWING_SYMBOLS.into_iter().filter(|symbol| !self.rules.contains_key(symbol)).collect();
+            for (symbol, productions) in self.rules.iter() {
+                let draws = productions.iter()
+                    .filter(|production| production.weight > 0)
+                    .any(|production| production.to.chars().any(|to| drawing.contains(&to)));
+                if draws {
+                    next.insert(*symbol);
+                }
+            }
+
+            if next == drawing { break; }
+            drawing = next;
+        }
+
+        if !self.axiom.chars().any(|symbol| drawing.contains(&symbol)) {
+            return Err("the axiom never grows into anything that draws".to_string());
+        }
+        Ok(self)
+    }
+
+    fn rewrite(&self, rng: &mut impl Rng) -> String {
+        let mut sentence = self.axiom.clone();
+
+        for _ in 0..self.iterations {
+            let mut next = String::new();
+
+            for symbol in sentence.chars() {
+                let Some(productions) = self.rules.get(&symbol) else {
+                    next.push(symbol);
+                    continue;
+                };
+
+                let total: u32 = productions.iter().map(|production| production.weight).sum();
+                if total == 0 { continue; }
+
+                let mut roll = rng.gen_range(0..total);
+                for production in productions.iter() {
+                    if roll < production.weight {
+                        next.push_str(&production.to);
+                        break;
+                    }
+                    roll -= production.weight;
+                }
+            }
+
+            sentence = next;
+            if sentence.len() > MAX_SENTENCE_LENGTH {
+                warn!("tree sentence grew past {MAX_SENTENCE_LENGTH} symbols, check the species rules");
+                break;
+            }
+        }
+
+        sentence
+    }
+
+    fn draw_canopy(&self, cells: &mut HashMap<(i32, i32), Cell>, rng: &mut impl Rng, (x, y): (i32, i32), shrink: i32) {
+        let leaf = |cells: &mut HashMap<(i32, i32), Cell>, position: (i32, i32)| {
+            cells.entry(position).or_insert(Cell::Leaves);
+        };
+
+        match self.canopy {
+            Canopy::Round { radius } => {
+                let r = (radius - shrink).max(1);
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        if dx * dx + dy * dy <= r * r + 1 {
+                            leaf(cells, (x + dx, y + dy));
+                        }
+                    }
+                }
+            },
+            Canopy::Cone { radius, height } => {
+                let (r, h) = ((radius - shrink).max(1), (height - shrink).max(2));
+                for row in 0..h {
+                    let half = r * (row + 1) / h;
+                    for dx in -half..=half {
+                        leaf(cells, (x + dx, y + 1 - row + h / 2));
+                    }
+                }
+            },
+            Canopy::Flat { radius } => {
+                let r = (radius - shrink).max(1);
+                let half_height = (r / 2).max(1);
+                for dy in -half_height..=half_height {
+                    for dx in -r..=r {
+                        if dx * dx + 4 * dy * dy <= r * r + 1 {
+                            leaf(cells, (x + dx, y + dy + 1));
+                        }
+                    }
+                }
+            },
+            Canopy::Blob { radius, density } => {
+                let r = (radius - shrink).max(1);
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        let inner = dx * dx + dy * dy <= (r - 1) * (r - 1);
+                        if dx * dx + dy * dy <= r * r + 1 && (inner || rng.gen_bool(density)) {
+                            leaf(cells, (x + dx, y + dy));
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    /// Grows a tree whose trunk base is the anchor, so it can be placed right on a surface block.
+    /// The turtle starts there facing up
+    pub fn grow(&self, rng: &mut impl Rng, block_database: &BlockDatabase) -> Option<BlockStructure> {
+        let sentence = self.rewrite(rng);
+
+        let mut cells: HashMap<(i32, i32), Cell> = HashMap::new();
+        let mut stack = vec![];
+        let (mut position, mut direction) = ((0, 0), 0usize);
+
+        // a background log never replaces a log where a branch starts on the trunk
+        let put = |cells: &mut HashMap<(i32, i32), Cell>, position: (i32, i32), cell: Cell| {
+            if cell == Cell::Log || cells.get(&position) != Some(&Cell::Log) {
+                cells.insert(position, cell);
+            }
+        };
+
+        for symbol in sentence.chars() {
+            match symbol {
+                'F' | 'f' => {
+                    let cell = if symbol == 'F' { Cell::Log } else { Cell::BackgroundLog };
+                    let (dx, dy) = DIRECTIONS[direction];
+
+                    put(&mut cells, position, cell);
+                    // diagonal steps go through the side cell too, so branches stay connected
+                    if dx != 0 && dy != 0 {
+                        put(&mut cells, (position.0 + dx, position.1), cell);
+                    }
+                    position = (position.0 + dx, position.1 + dy);
+                    put(&mut cells, position, cell);
+                },
+                '+' => direction = (direction + 1) % 8,
+                '-' => direction = (direction + 7) % 8,
+                '|' => direction = (direction + 4) % 8,
+                '[' => stack.push((position, direction)),
+                ']' => if let Some(state) = stack.pop() {
+                    (position, direction) = state;
+                },
+                'L' => self.draw_canopy(&mut cells, rng, position, 0),
+                'l' => self.draw_canopy(&mut cells, rng, position, 1),
+                _ => {}
+            }
+        }
+
+        if cells.is_empty() { return None; }
+
+        // the trunk base stays inside the structure, so the anchor is there even if nothing was drawn on it
+        let min_x = cells.keys().map(|(x, _)| *x).fold(0, i32::min);
+        let max_x = cells.keys().map(|(x, _)| *x).fold(0, i32::max);
+        let min_y = cells.keys().map(|(_, y)| *y).fold(0, i32::min);
+        let max_y = cells.keys().map(|(_, y)| *y).fold(0, i32::max);
+
+        let (width, height) = ((max_x - min_x + 1) as usize, (max_y - min_y + 1) as usize);
+
+        let block_id = |name: &str| block_database.get_by_name(name).map(|block| block.id).unwrap_or_else(|| {
+            warn!("unknown block \"{name}\" in a tree species");
+            0
+        });
+        let (log, leaves) = (block_id(&self.log_block), block_id(&self.leaves_block));
+
+        let mut data = vec![vec![0; width]; height];
+        let mut bg_data = vec![vec![0; width]; height];
+
+        for (&(x, y), cell) in cells.iter() {
+            let (x, y) = ((x - min_x) as usize, (y - min_y) as usize);
+            match cell {
+                Cell::Log => {
+                    data[y][x] = log;
+                    bg_data[y][x] = log;
+                },
+                Cell::BackgroundLog => bg_data[y][x] = log,
+                Cell::Leaves => data[y][x] = leaves,
+            }
+        }
+
+        Some(BlockStructure {
+            data,
+            bg_data,
+            air_mask: vec![vec![false; width]; height],
+            anchor: (-min_x as usize, -min_y as usize),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::world::chunk::block::Block;
+
+    fn species(json: &str) -> Result<TreeSpecies, String> {
+        serde_json::from_str::<TreeSpecies>(json).map_err(|err| err.to_string()).and_then(TreeSpecies::validate)
+    }
+
+    fn blob(density: f64) -> Result<TreeSpecies, String> {
+        species(&format!(r#"{{ "log_block": "rustaria:log", "leaves_block": "rustaria:leaves", "axiom": "FL", "rules": {{}},
+            "iterations": 0, "canopy": {{ "shape": "blob", "radius": 2, "density": {density} }} }}"#))
+    }
+
+    #[test]
+    fn blob_density_has_to_be_a_chance() {
+        assert!(blob(0.0).is_ok());
+        assert!(blob(1.0).is_ok());
+        assert_eq!(blob(1.5).err().as_deref(), Some("canopy density 1.5 isn't between 0 and 1"));
+        assert_eq!(blob(-0.1).err().as_deref(), Some("canopy density -0.1 isn't between 0 and 1"));
+    }
+
+    #[test]
+    fn species_that_never_draws_is_rejected() {
+        let rewritten_away = species(r#"{ "log_block": "rustaria:log", "leaves_block": "rustaria:leaves", "axiom": "AF",
+            "rules": { "A": [{ "to": "B" }], "F": [{ "to": "+" }] }, "iterations": 2, "canopy": { "shape": "round", "radius": 2 } }"#);
+        assert!(rewritten_away.is_err());
+
+        let drawn_late = species(r#"{ "log_block": "rustaria:log", "leaves_block": "rustaria:leaves", "axiom": "A",
+            "rules": { "A": [{ "to": "B" }], "B": [{ "to": "F" }] }, "iterations": 2, "canopy": { "shape": "round", "radius": 2 } }"#);
+        assert!(drawn_late.is_ok());
+    }
+
+    #[test]
+    fn canopy_above_the_origin_keeps_the_anchor_on_it() {
+        let species = species(r#"{ "log_block": "rustaria:log", "leaves_block": "rustaria:leaves", "axiom": "L",
+            "rules": {}, "iterations": 0, "canopy": { "shape": "cone", "radius": 1, "height": 2 } }"#).unwrap();
+        let names = ["rustaria:air", "rustaria:log", "rustaria:leaves"].map(String::from).to_vec();
+        let block_database = BlockDatabase::new(vec![Block::AIR, Block { id: 1, ..Block::AIR }, Block { id: 2, ..Block::AIR }], names, vec![None; 3]);
+
+        let tree = species.grow(&mut StdRng::seed_from_u64(0), &block_database).unwrap();
+
+        // the cone is drawn on rows 1 and 2, so the structure starts one row under it
+        assert_eq!(tree.anchor, (1, 0));
+        assert_eq!(tree.height(), 3);
+        assert!(tree.data[0].iter().all(|id| *id == 0));
+    }
+}
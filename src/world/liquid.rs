@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{BLOCK_SIZE_PX, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+use super::{
+    chunk::{block::{Block, MAX_LIQUID_LEVEL}, generate_chunk_data, light::{update_block_light, UpdateBlockLight}, GenerateChunkData},
+    set_block_at_position,
+    SetBlock,
+    World
+};
+
+/// Seconds between two liquid ticks
+const TICK_SECONDS: f32 = 0.1;
+
+/// Furthest a liquid levels out to each side in one move
+const MAX_SPREAD: i32 = 8;
+
+pub struct LiquidPlugin;
+
+impl Plugin for LiquidPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LiquidSimulation>();
+        app.add_systems(Update, (
+            wake_liquids.after(generate_chunk_data).after(set_block_at_position),
+            flow_liquids.after(wake_liquids).before(update_block_light),
+        ));
+    }
+}
+
+#[derive(Resource)]
+pub struct LiquidSimulation {
+    timer: Timer,
+    tick: u32,
+    /// World positions of cells that might flow on the next tick. Settled liquid isn't in here
+    active: HashSet<(i32, i32)>,
+}
+
+impl Default for LiquidSimulation {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(TICK_SECONDS, TimerMode::Repeating),
+            tick: 0,
+            active: HashSet::new(),
+        }
+    }
+}
+
+impl LiquidSimulation {
+    /// Makes a cell and its neighbours flow again, if they hold liquid
+    pub fn wake(&mut self, (x, y): (i32, i32)) {
+        self.active.extend([(x, y), (x+1, y), (x-1, y), (x, y+1), (x, y-1)]);
+    }
+}
+
+fn wake_liquids(
+    mut ev_generate: EventReader<GenerateChunkData>,
+    mut ev_set_block: EventReader<SetBlock>,
+    mut simulation: ResMut<LiquidSimulation>,
+    world: Res<World>,
+) {
+    for ev in ev_generate.read() {
+        let Some(chunk) = world.get_chunk(ev.position) else { continue };
+        let (left, bottom) = (ev.position.0 * CHUNK_WIDTH as i32, ev.position.1 * CHUNK_HEIGHT as i32);
+
+        for x in 0..CHUNK_WIDTH {
+            for y in 0..CHUNK_HEIGHT {
+                if chunk.data[x][y].is_liquid {
+                    simulation.active.insert((left + x as i32, bottom + y as i32));
+                }
+            }
+        }
+
+        // liquid in the neighbours couldn't flow into this chunk while it wasn't there
+        for x in -1..=CHUNK_WIDTH as i32 {
+            simulation.active.extend([(left + x, bottom - 1), (left + x, bottom + CHUNK_HEIGHT as i32)]);
+        }
+        for y in 0..CHUNK_HEIGHT as i32 {
+            simulation.active.extend([(left - 1, bottom + y), (left + CHUNK_WIDTH as i32, bottom + y)]);
+        }
+    }
+
+    for ev in ev_set_block.read() {
+        let block = (ev.position / BLOCK_SIZE_PX).floor();
+        simulation.wake((block.x as i32, block.y as i32));
+    }
+}
+
+/// How much of `liquid` fits into `target`. Liquids never mix or flow into other blocks
+fn room_for(liquid: Block, target: Block) -> u8 {
+    if target.id == 0 {
+        MAX_LIQUID_LEVEL
+    }
+    else if target.id == liquid.id {
+        MAX_LIQUID_LEVEL - target.level
+    }
+    else {
+        0
+    }
+}
+
+/// Moves liquid out of a cell, down as far as it fits, then levels out the row it can spread along.
+/// Returns the cells that changed, their chunks aren't marked as modified yet
+fn flow(world: &mut World, (x, y): (i32, i32), mut liquid: Block) -> Vec<(i32, i32)> {
+    let mut changed = vec![];
+
+    if let Some(mut below) = world.get_block_at((x, y-1)) {
+        let amount = room_for(liquid, below).min(liquid.level);
+        if amount > 0 {
+            if below.id == 0 {
+                below = liquid;
+                below.level = 0;
+            }
+            below.level += amount;
+            liquid.level -= amount;
+
+            world.replace_block_at((x, y-1), below);
+            world.replace_block_at((x, y), if liquid.level == 0 { Block::AIR } else { liquid });
+            changed.extend([(x, y-1), (x, y)]);
+        }
+    }
+
+    if liquid.level == 0 { return changed; }
+
+    let mut row = vec![(x, liquid.level)];
+    for dx in [-1, 1] {
+        for step in 1..=MAX_SPREAD {
+            let Some(side) = world.get_block_at((x + dx * step, y)) else { break };
+            if side.id != 0 && side.id != liquid.id { break; }
+            row.push((x + dx * step, if side.id == 0 { 0 } else { side.level }));
+        }
+    }
+
+    // a level apart is settled, otherwise a lone drop would wander forever
+    let levels = row.iter().map(|(_, level)| *level);
+    if levels.clone().max().unwrap_or(0) - levels.min().unwrap_or(0) <= 1 { return changed; }
+
+    let total: u32 = row.iter().map(|(_, level)| *level as u32).sum();
+    let (share, extra) = (total / row.len() as u32, total % row.len() as u32);
+
+    // the leftover levels stay closest to where the liquid came from
+    row.sort_by_key(|(cell_x, _)| ((cell_x - x).abs(), *cell_x));
+
+    for (i, (cell_x, level)) in row.into_iter().enumerate() {
+        let new_level = (share + (i < extra as usize) as u32) as u8;
+        if new_level == level { continue; }
+
+        let mut block = liquid;
+        block.level = new_level;
+        world.replace_block_at((cell_x, y), if new_level == 0 { Block::AIR } else { block });
+        changed.push((cell_x, y));
+    }
+
+    changed
+}
+
+fn flow_liquids(
+    time: Res<Time>,
+    mut simulation: ResMut<LiquidSimulation>,
+    mut world: ResMut<World>,
+    mut ev_update_light: EventWriter<UpdateBlockLight>,
+) {
+    if !simulation.timer.tick(time.delta()).just_finished() { return; }
+    simulation.tick = simulation.tick.wrapping_add(1);
+    let tick = simulation.tick;
+
+    // bottom up, so a falling column makes room for itself within one tick
+    let mut cells: Vec<(i32, i32)> = simulation.active.drain().collect();
+    cells.sort_by_key(|&(x, y)| (y, x));
+
+    for position in cells {
+        let Some(block) = world.get_block_at(position) else { continue };
+        if !block.is_liquid { continue; }
+
+        if !tick.is_multiple_of(block.viscosity.max(1) as u32) {
+            simulation.active.insert(position);
+            continue;
+        }
+
+        let changed = flow(&mut world, position, block);
+
+        // generated liquid settles the same way every time its chunk is generated, so a move inside one unsaved chunk
+        // stays out of the save. A move across a chunk border or into a saved chunk saves every chunk it touched,
+        // otherwise the liquid would be generated again on the side that isn't saved
+        let mut chunks: Vec<(i32, i32)> = changed.iter().map(|&cell| World::locate_block(cell).0).collect();
+        chunks.sort_unstable();
+        chunks.dedup();
+        if chunks.len() > 1 || chunks.iter().any(|&chunk| world.is_modified(chunk)) {
+            for &cell in changed.iter() {
+                world.mark_modified(cell);
+            }
+        }
+
+        for cell in changed {
+            simulation.wake(cell);
+            ev_update_light.send(UpdateBlockLight { position: cell });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::world::chunk::Chunk;
+
+    const STONE: Block = Block { id: 1, is_solid: true, ..Block::AIR };
+    const WATER: Block = Block { id: 2, is_liquid: true, viscosity: 1, level: MAX_LIQUID_LEVEL, ..Block::AIR };
+    const LAVA: Block = Block { id: 3, is_liquid: true, viscosity: 5, level: MAX_LIQUID_LEVEL, ..Block::AIR };
+
+    /// One empty chunk with a stone floor along its bottom row
+    fn floored_world() -> World {
+        let mut world = World::default();
+        world.chunks.insert((0, 0), Chunk::new((0, 0)));
+        for x in 0..CHUNK_WIDTH as i32 {
+            world.replace_block_at((x, 0), STONE);
+        }
+        world
+    }
+
+    fn level(world: &World, position: (i32, i32)) -> u8 {
+        let block = world.get_block_at(position).unwrap();
+        if block.is_liquid { block.level } else { 0 }
+    }
+
+    #[test]
+    fn liquid_falls_into_the_air_under_it() {
+        let mut world = floored_world();
+        world.replace_block_at((5, 5), WATER);
+
+        let changed = flow(&mut world, (5, 5), WATER);
+
+        assert_eq!(changed, [(5, 4), (5, 5)]);
+        assert_eq!(level(&world, (5, 4)), MAX_LIQUID_LEVEL);
+        assert_eq!(world.get_block_at((5, 5)).unwrap().id, 0);
+    }
+
+    #[test]
+    fn liquid_levels_out_no_further_than_max_spread() {
+        let mut world = floored_world();
+        // a shallow sheet of water with a full cell in the middle
+        for x in 0..CHUNK_WIDTH as i32 {
+            world.replace_block_at((x, 1), Block { level: 2, ..WATER });
+        }
+        world.replace_block_at((16, 1), WATER);
+        let total = |world: &World| (0..CHUNK_WIDTH as i32).map(|x| level(world, (x, 1)) as u32).sum::<u32>();
+        let before = total(&world);
+
+        let changed = flow(&mut world, (16, 1), WATER);
+
+        assert!(!changed.is_empty());
+        assert!(changed.iter().all(|&(x, _)| (x - 16).abs() <= MAX_SPREAD), "{changed:?}");
+        assert_eq!(level(&world, (16 - MAX_SPREAD - 1, 1)), 2);
+        assert_eq!(level(&world, (16 + MAX_SPREAD + 1, 1)), 2);
+        assert_eq!(level(&world, (16, 1)), 3);
+        assert_eq!(total(&world), before);
+    }
+
+    #[test]
+    fn viscous_liquid_moves_only_every_few_ticks() {
+        let mut world = floored_world();
+        world.replace_block_at((5, 5), WATER);
+        world.replace_block_at((20, 5), LAVA);
+        let mut simulation = LiquidSimulation::default();
+        simulation.wake((5, 5));
+        simulation.wake((20, 5));
+
+        let mut app = App::new();
+        app
+            .add_event::<UpdateBlockLight>()
+            .init_resource::<Time>()
+            .insert_resource(world)
+            .insert_resource(simulation)
+            .add_systems(Update, flow_liquids);
+
+        let tick = |app: &mut App| {
+            app.world_mut().resource_mut::<Time>().advance_by(Duration::from_secs_f32(TICK_SECONDS));
+            app.update();
+        };
+
+        tick(&mut app);
+        let world = app.world().resource::<World>();
+        assert_eq!(level(world, (5, 4)), MAX_LIQUID_LEVEL);
+        assert_eq!(level(world, (20, 5)), MAX_LIQUID_LEVEL);
+
+        for _ in 1..LAVA.viscosity {
+            tick(&mut app);
+        }
+        let world = app.world().resource::<World>();
+        assert_eq!(level(world, (20, 4)), MAX_LIQUID_LEVEL);
+        assert_eq!(level(world, (20, 5)), 0);
+    }
+}
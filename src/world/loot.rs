@@ -0,0 +1,291 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    inventory::item::{Item, ItemDatabase, Tool, ToolKind},
+    pack::ContentPacks,
+    registry::{DataFile, RegistriesReloaded, RegistryFiles, LOOT_TABLES_FILE}
+};
+
+use super::chunk::block::BlockDatabase;
+
+pub struct LootPlugin;
+
+impl Plugin for LootPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Startup, init_loot_tables)
+            .add_systems(Update, reload_loot_tables);
+    }
+}
+
+/// Has to hold for a pool or an entry to be used, checked against the tool the block was broken with
+#[derive(Deserialize)]
+#[serde(tag = "condition", rename_all = "snake_case")]
+pub enum LootCondition {
+    Tool { kind: ToolKind },
+    MinTier { tier: u8 },
+    SilkTouch,
+    NoSilkTouch,
+}
+
+impl LootCondition {
+    fn holds(&self, tool: Option<Tool>) -> bool {
+        match self {
+            Self::Tool { kind } => tool.is_some_and(|tool| tool.kind == *kind),
+            Self::MinTier { tier } => tool.is_some_and(|tool| tool.tier >= *tier),
+            Self::SilkTouch => tool.is_some_and(|tool| tool.silk_touch),
+            Self::NoSilkTouch => !tool.is_some_and(|tool| tool.silk_touch),
+        }
+    }
+}
+
+fn default_one() -> u32 {
+    1
+}
+
+fn default_count() -> (u32, u32) {
+    (1, 1)
+}
+
+#[derive(Deserialize)]
+pub struct LootEntry {
+    /// Item name, or nothing for an entry that only takes up weight
+    #[serde(default)]
+    pub item: Option<String>,
+    #[serde(default = "default_one")]
+    pub weight: u32,
+    /// Smallest and largest amount dropped, both inclusive
+    #[serde(default = "default_count")]
+    pub count: (u32, u32),
+    #[serde(default)]
+    pub conditions: Vec<LootCondition>,
+}
+
+/// Picks `rolls` entries by weight, out of the ones whose conditions hold
+#[derive(Deserialize)]
+pub struct LootPool {
+    #[serde(default = "default_one")]
+    pub rolls: u32,
+    #[serde(default)]
+    pub conditions: Vec<LootCondition>,
+    pub entries: Vec<LootEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct LootTable {
+    /// Block name
+    pub block: String,
+    pub pools: Vec<LootPool>,
+}
+
+impl LootTable {
+    /// Items and amounts dropped by one break. The same item can come up more than once
+    pub fn roll(&self, tool: Option<Tool>, rng: &mut impl Rng, item_database: &ItemDatabase) -> Vec<(Item, u32)> {
+        let mut drops = vec![];
+
+        for pool in self.pools.iter() {
+            if !pool.conditions.iter().all(|condition| condition.holds(tool)) { continue; }
+
+            let entries: Vec<&LootEntry> = pool.entries.iter()
+                .filter(|entry| entry.conditions.iter().all(|condition| condition.holds(tool)))
+                .collect();
+
+            let total: u32 = entries.iter().map(|entry| entry.weight).sum();
+            if total == 0 { continue; }
+
+            for _ in 0..pool.rolls {
+                let mut roll = rng.gen_range(0..total);
+                let Some(entry) = entries.iter().find(|entry| {
+                    if roll < entry.weight { return true; }
+                    roll -= entry.weight;
+                    false
+                }) else { continue };
+
+                let Some(item) = entry.item.as_ref().and_then(|name| item_database.get_by_name(name)) else { continue };
+                let amount = rng.gen_range(entry.count.0..=entry.count.1.max(entry.count.0));
+                if amount > 0 {
+                    drops.push((item, amount));
+                }
+            }
+        }
+
+        drops
+    }
+}
+
+#[derive(Deserialize)]
+struct LootTableData {
+    tables: Vec<LootTable>,
+}
+
+/// Loot tables by block id. Blocks without one drop their `drop_item`
+#[derive(Resource)]
+pub struct LootTables(HashMap<u32, LootTable>);
+
+impl LootTables {
+    pub fn get(&self, block: u32) -> Option<&LootTable> {
+        self.0.get(&block)
+    }
+}
+
+/// Loot tables of every pack, `read` giving the contents of a file. Files and tables that can't be used are logged and skipped
+fn load_loot_tables(
+    packs: &ContentPacks,
+    block_database: &BlockDatabase,
+    item_database: &ItemDatabase,
+    read: impl Fn(&Path) -> Result<String, String>,
+) -> LootTables {
+    let mut by_block = HashMap::new();
+
+    for (_, path) in packs.files(LOOT_TABLES_FILE) {
+        let string = match read(&path) {
+            Ok(string) => string,
+            Err(err) => {
+                error!("{}: can't be read: {err}", path.display());
+                continue;
+            },
+        };
+
+        let tables = match serde_json::from_str::<LootTableData>(&string) {
+            Ok(data) => data.tables,
+            Err(err) => {
+                error!("{}: isn't valid: {err}", path.display());
+                continue;
+            },
+        };
+
+        for table in tables {
+            let Some(block) = block_database.get_by_name(&table.block) else {
+                error!("{}: loot table for unknown block \"{}\"", path.display(), table.block);
+                continue;
+            };
+
+            let items = table.pools.iter().flat_map(|pool| pool.entries.iter()).filter_map(|entry| entry.item.as_ref());
+            for item in items.filter(|item| item_database.get_by_name(item).is_none()) {
+                error!("{}: loot table of \"{}\" drops unknown item \"{item}\"", path.display(), table.block);
+            }
+
+            // a later pack's table for the block replaces the earlier one
+            by_block.insert(block.id, table);
+        }
+    }
+
+    LootTables(by_block)
+}
+
+fn init_loot_tables(
+    mut commands: Commands,
+    block_database: Res<BlockDatabase>,
+    item_database: Res<ItemDatabase>,
+    packs: Res<ContentPacks>,
+) {
+    commands.insert_resource(load_loot_tables(&packs, &block_database, &item_database, |path| {
+        fs::read_to_string(path).map_err(|err| err.to_string())
+    }));
+}
+
+/// Loot tables are read again with the block and item data, the loot table file may be what changed
+fn reload_loot_tables(
+    mut ev_reloaded: EventReader<RegistriesReloaded>,
+    mut loot_tables: ResMut<LootTables>,
+    block_database: Res<BlockDatabase>,
+    item_database: Res<ItemDatabase>,
+    packs: Res<ContentPacks>,
+    files: Res<RegistryFiles>,
+    data_files: Res<Assets<DataFile>>,
+) {
+    if ev_reloaded.read().count() == 0 { return; }
+
+    *loot_tables = load_loot_tables(&packs, &block_database, &item_database, |path| files.read(&data_files, path));
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    const PICKAXE: Tool = Tool { kind: ToolKind::Pickaxe, tier: 2, mining_speed: 4., effective: 0, durability: 100, silk_touch: false };
+    const SILK_PICKAXE: Tool = Tool { silk_touch: true, ..PICKAXE };
+
+    fn items() -> ItemDatabase {
+        let items = (0..2).map(|id| Item { id, ..default() }).collect();
+        let names = ["rustaria:coal", "rustaria:ore"].map(String::from).to_vec();
+        ItemDatabase::new(items, names, vec![String::new(); 2])
+    }
+
+    fn table(pools: &str) -> LootTable {
+        serde_json::from_str(&format!(r#"{{ "block": "rustaria:ore", "pools": {pools} }}"#)).unwrap()
+    }
+
+    /// Every drop of `rolls` breaks, as item ids
+    fn drops(table: &LootTable, tool: Option<Tool>, rolls: usize) -> Vec<(u32, u32)> {
+        let (items, mut rng) = (items(), StdRng::seed_from_u64(1));
+        (0..rolls)
+            .flat_map(|_| table.roll(tool, &mut rng, &items))
+            .map(|(item, amount)| (item.id, amount))
+            .collect()
+    }
+
+    #[test]
+    fn pool_conditions_hold_back_the_whole_pool() {
+        let table = table(r#"[{
+            "conditions": [{ "condition": "tool", "kind": "pickaxe" }, { "condition": "min_tier", "tier": 2 }],
+            "entries": [{ "item": "coal" }]
+        }]"#);
+
+        assert_eq!(drops(&table, None, 1), []);
+        assert_eq!(drops(&table, Some(Tool { tier: 1, ..PICKAXE }), 1), []);
+        assert_eq!(drops(&table, Some(Tool { kind: ToolKind::Axe, ..PICKAXE }), 1), []);
+        assert_eq!(drops(&table, Some(PICKAXE), 1), [(0, 1)]);
+    }
+
+    #[test]
+    fn entries_are_picked_only_when_their_conditions_hold() {
+        let table = table(r#"[{
+            "rolls": 3,
+            "entries": [
+                { "item": "ore", "conditions": [{ "condition": "silk_touch" }] },
+                { "item": "coal", "conditions": [{ "condition": "no_silk_touch" }] }
+            ]
+        }]"#);
+
+        assert_eq!(drops(&table, Some(PICKAXE), 1), [(0, 1); 3]);
+        assert_eq!(drops(&table, Some(SILK_PICKAXE), 1), [(1, 1); 3]);
+        assert_eq!(drops(&table, None, 1), [(0, 1); 3]);
+    }
+
+    #[test]
+    fn entries_are_picked_by_weight_with_a_count_in_range() {
+        let table = table(r#"[{
+            "entries": [
+                { "item": "coal", "weight": 3, "count": [2, 4] },
+                { "weight": 1 }
+            ]
+        }]"#);
+
+        let drops = drops(&table, None, 1000);
+
+        // the empty entry takes a quarter of the rolls
+        assert!((650..850).contains(&drops.len()), "{} drops", drops.len());
+        assert!(drops.iter().all(|&(item, amount)| item == 0 && (2..=4).contains(&amount)));
+        for amount in 2..=4 {
+            assert!(drops.iter().any(|drop| drop.1 == amount));
+        }
+    }
+
+    #[test]
+    fn every_pool_rolls_on_its_own() {
+        let table = table(r#"[
+            { "entries": [{ "item": "ore" }] },
+            { "rolls": 2, "entries": [{ "item": "coal", "count": [0, 0] }] }
+        ]"#);
+
+        // entries rolling no items drop nothing
+        assert_eq!(drops(&table, None, 1), [(1, 1)]);
+    }
+}
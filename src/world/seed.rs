@@ -0,0 +1,60 @@
+use std::{fs::File, io::Read};
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde_json::Value;
+
+const CONFIG_PATH: &str = "config.json";
+
+/// Text the world is generated from. Any string works, numbers are not treated specially
+#[derive(Resource, Clone, PartialEq, Debug)]
+pub struct WorldSeed(pub String);
+
+impl WorldSeed {
+    /// `--seed <text>` or `--seed=<text>`
+    pub fn from_args() -> Option<Self> {
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            if arg == "--seed" {
+                return args.next().map(Self);
+            }
+            if let Some(seed) = arg.strip_prefix("--seed=") {
+                return Some(Self(seed.to_string()));
+            }
+        }
+
+        None
+    }
+
+    /// `"seed"` field of config.json in the working directory
+    pub fn from_config() -> Option<Self> {
+        let mut file = File::open(CONFIG_PATH).ok()?;
+
+        let mut string = String::new();
+        let _ = file.read_to_string(&mut string);
+
+        let map = serde_json::from_str::<serde_json::Map<String, Value>>(&string).ok()?;
+
+        match map.get("seed")? {
+            Value::String(seed) => Some(Self(seed.clone())),
+            Value::Number(seed) => Some(Self(seed.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn random() -> Self {
+        Self(rand::thread_rng().gen::<u32>().to_string())
+    }
+
+    /// 32-bit FNV-1a over the UTF-8 bytes of the seed.
+    /// Unlike `DefaultHasher` it's fixed by its spec, so it gives the same terrain on every machine and toolchain
+    pub fn hash(&self) -> u32 {
+        const FNV_OFFSET_BASIS: u32 = 0x811c9dc5;
+        const FNV_PRIME: u32 = 0x01000193;
+
+        self.0.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(FNV_PRIME)
+        })
+    }
+}